@@ -0,0 +1,180 @@
+//! `#[derive(FromCallbackParams)]` and `#[derive(IntoCallbackParams)]` for the `callback` crate
+//!
+//! Enable the `derive` feature of `callback` rather than depending on this crate directly.
+//!
+//! Every field is mapped to the action param of the same name and has to implement `ParamValue`.
+//! Fields can be customized with `#[callback(...)]`:
+//!
+//! - `rename = "name"` maps the field to the param `name` instead
+//! - `default` fills the field with `Default::default()` when the param is missing
+//!
+//! `Option` fields are `None` when the param is missing and are left out when `None`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, GenericArgument, LitStr, Type};
+
+#[proc_macro_derive(FromCallbackParams, attributes(callback))]
+pub fn derive_from_callback_params(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    from_callback_params(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(IntoCallbackParams, attributes(callback))]
+pub fn derive_into_callback_params(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    into_callback_params(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+struct Field {
+    ident: syn::Ident,
+    key: String,
+    default: bool,
+    /// `T` of an `Option<T>` field
+    option: Option<Type>,
+    ty: Type,
+}
+
+fn fields(input: &DeriveInput) -> Result<Vec<Field>, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    input.span(),
+                    "callback params can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "callback params can only be derived for structs",
+            ))
+        }
+    };
+
+    fields
+        .iter()
+        .map(|field| {
+            let ident = field.ident.clone().unwrap();
+            let mut key = ident.to_string().trim_start_matches("r#").to_string();
+            let mut default = false;
+            for attr in field.attrs.iter().filter(|a| a.path().is_ident("callback")) {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("rename") {
+                        key = meta.value()?.parse::<LitStr>()?.value();
+                        Ok(())
+                    } else if meta.path.is_ident("default") {
+                        default = true;
+                        Ok(())
+                    } else {
+                        Err(meta.error("expected `rename = \"...\"` or `default`"))
+                    }
+                })?;
+            }
+            Ok(Field {
+                ident,
+                key,
+                default,
+                option: option_type(&field.ty),
+                ty: field.ty.clone(),
+            })
+        })
+        .collect()
+}
+
+fn option_type(ty: &Type) -> Option<Type> {
+    let segment = match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.segments.last()?,
+        _ => return None,
+    };
+    if segment.ident != "Option" {
+        return None;
+    }
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) if args.args.len() == 1 => {
+            match args.args.first()? {
+                GenericArgument::Type(ty) => Some(ty.clone()),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn from_callback_params(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let fields = fields(input)?.into_iter().map(|field| {
+        let Field {
+            ident,
+            key,
+            default,
+            option,
+            ty,
+        } = field;
+        let value = match option {
+            Some(inner) => quote! { params.get_as::<#inner>(#key)? },
+            None if default => quote! {
+                params.get_as::<#ty>(#key)?.unwrap_or_default()
+            },
+            None => quote! {
+                params.get_as::<#ty>(#key)?.ok_or_else(|| {
+                    ::callback::x_callback_url::XCallbackError::MissingParam(#key.to_string())
+                })?
+            },
+        };
+        quote! { #ident: #value }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::callback::x_callback_url::FromCallbackParams for #name #ty_generics
+        #where_clause
+        {
+            fn from_callback_params(
+                params: &::callback::x_callback_url::ActionParams,
+            ) -> ::std::result::Result<Self, ::callback::x_callback_url::XCallbackError> {
+                ::std::result::Result::Ok(#name {
+                    #(#fields,)*
+                })
+            }
+        }
+    })
+}
+
+fn into_callback_params(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let fields = fields(input)?.into_iter().map(|field| {
+        let Field {
+            ident, key, option, ..
+        } = field;
+        match option {
+            Some(_) => quote! {
+                if let ::std::option::Option::Some(value) = &self.#ident {
+                    params.push_as(#key, value);
+                }
+            },
+            None => quote! { params.push_as(#key, &self.#ident); },
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::callback::x_callback_url::IntoCallbackParams for #name #ty_generics
+        #where_clause
+        {
+            fn into_callback_params(self) -> ::callback::x_callback_url::ActionParams {
+                let mut params = ::callback::x_callback_url::ActionParams::default();
+                #(#fields)*
+                params
+            }
+        }
+    })
+}
@@ -0,0 +1,209 @@
+use percent_encoding::percent_decode_str;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+const FILE_PREFIX: char = '@';
+const BASE64_FILE_PREFIX: &str = "@@";
+const ESCAPED_FILE_PREFIX: &str = "\\@";
+const STDIN: &str = "-";
+const FILENAME_KEY: &str = "filename";
+
+/// Positional `params` with `@file`, `@-` and `@@file` values replaced by what they refer to,
+/// followed by `options`, which are taken as is
+///
+/// `@file` reads a text file and `@-` reads `stdin`, which can only be done once. `@@file` base64
+/// encodes a file and adds a `filename` param with its name, unless one is given. A leading `\@`
+/// is a literal `@`. With `encoded`, keys and values that are not references are decoded, and
+/// what they decode to is taken as is.
+pub fn read_values<R: Read>(
+    params: Vec<(String, String)>,
+    options: Vec<(String, String)>,
+    encoded: bool,
+    mut stdin: R,
+) -> Result<Vec<(String, String)>, String> {
+    let mut has_filename = params
+        .iter()
+        .chain(&options)
+        .any(|(k, _)| k == FILENAME_KEY);
+    let mut stdin_read = false;
+    let mut values = Vec::with_capacity(params.len() + options.len());
+
+    for (key, value) in params {
+        let key = if encoded { decode(&key) } else { key };
+        let error = |e| format!("Unable to read {} for {}: {}", value, key, e);
+
+        if let Some(literal) = value.strip_prefix(ESCAPED_FILE_PREFIX) {
+            let literal = format!("{}{}", FILE_PREFIX, literal);
+            let value = if encoded { decode(&literal) } else { literal };
+            values.push((key, value));
+        } else if encoded && !value.starts_with(FILE_PREFIX) {
+            values.push((key, decode(&value)));
+        } else if let Some(path) = value.strip_prefix(BASE64_FILE_PREFIX) {
+            let data = fs::read(path).map_err(error)?;
+            let filename = Path::new(path)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned());
+            values.push((key, base64::encode(data)));
+            if let (Some(filename), false) = (filename, has_filename) {
+                values.push((FILENAME_KEY.to_string(), filename));
+                has_filename = true;
+            }
+        } else if let Some(path) = value.strip_prefix(FILE_PREFIX) {
+            let text = if path == STDIN {
+                if stdin_read {
+                    return Err(format!("Stdin can only be read once, for {}", key));
+                }
+                stdin_read = true;
+                let mut text = String::new();
+                stdin.read_to_string(&mut text).map_err(error)?;
+                text
+            } else {
+                fs::read_to_string(path).map_err(error)?
+            };
+            values.push((key, text));
+        } else {
+            values.push((key, value));
+        }
+    }
+
+    values.extend(options);
+    Ok(values)
+}
+
+/// Decode a URL encoded key or value, treating `+` as a space like query strings do
+fn decode(src: &str) -> String {
+    percent_decode_str(&src.replace('+', " "))
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::input::read_values;
+    use std::env;
+    use std::fs;
+    use std::io;
+
+    fn params(params: &[(&str, &str)]) -> Vec<(String, String)> {
+        params
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_read_values() {
+        let dir = env::temp_dir().join(format!("callback-input-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let text = dir.join("notes.md");
+        let image = dir.join("image.png");
+        fs::write(&text, "# Notes\n").unwrap();
+        fs::write(&image, [0x89, b'P', b'N', b'G']).unwrap();
+
+        let input = params(&[
+            ("title", "Notes"),
+            ("text", &format!("@{}", text.display())),
+            ("file", &format!("@@{}", image.display())),
+            ("stdin", "@-"),
+        ]);
+        let values = read_values(input, vec![], false, "from stdin".as_bytes());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            values.unwrap(),
+            params(&[
+                ("title", "Notes"),
+                ("text", "# Notes\n"),
+                ("file", "iVBORw=="),
+                ("filename", "image.png"),
+                ("stdin", "from stdin"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_read_values_explicit_filename() {
+        let dir = env::temp_dir().join(format!("callback-filename-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let image = dir.join("image.png");
+        fs::write(&image, [0x89, b'P', b'N', b'G']).unwrap();
+        let file = format!("@@{}", image.display());
+
+        let values = read_values(
+            params(&[("filename", "a.png"), ("file", &file)]),
+            vec![],
+            false,
+            io::empty(),
+        );
+        // A filename option counts too
+        let option_values = read_values(
+            params(&[("file", &file)]),
+            params(&[("filename", "a.png")]),
+            false,
+            io::empty(),
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            values.unwrap(),
+            params(&[("filename", "a.png"), ("file", "iVBORw==")])
+        );
+        assert_eq!(
+            option_values.unwrap(),
+            params(&[("file", "iVBORw=="), ("filename", "a.png")])
+        );
+    }
+
+    #[test]
+    fn test_read_values_literal() {
+        let input = params(&[("handle", "\\@john"), ("email", "a%40b.com")]);
+        let options = params(&[("mention", "@john"), ("text", "@-")]);
+
+        assert_eq!(
+            read_values(input.clone(), options.clone(), false, io::empty()).unwrap(),
+            params(&[
+                ("handle", "@john"),
+                ("email", "a%40b.com"),
+                ("mention", "@john"),
+                ("text", "@-"),
+            ])
+        );
+        // Values that decode to @... are not read from files
+        assert_eq!(
+            read_values(
+                params(&[
+                    ("handle", "%40john"),
+                    ("escaped", "\\@a%20b"),
+                    ("a+b", "c+d")
+                ]),
+                options,
+                true,
+                io::empty(),
+            )
+            .unwrap(),
+            params(&[
+                ("handle", "@john"),
+                ("escaped", "@a b"),
+                ("a b", "c d"),
+                ("mention", "@john"),
+                ("text", "@-"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_read_values_errors() {
+        let read = |input| read_values(params(input), vec![], false, io::empty());
+        assert!(read(&[("a", "@-"), ("b", "@-")]).is_err());
+        assert!(read(&[("a", "@/does/not/exist")]).is_err());
+        // Encoded references are still read
+        assert!(read_values(
+            params(&[("a", "@/does/not/exist")]),
+            vec![],
+            true,
+            io::empty()
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,415 @@
+#![cfg_attr(
+    not(any(
+        all(feature = "macos", target_os = "macos"),
+        all(feature = "linux", target_os = "linux")
+    )),
+    allow(dead_code)
+)]
+
+use crate::output::{format_response, format_url, OutputFormat};
+use callback::client::{XCallbackClient, XCallbackStatus};
+use callback::x_callback_url::*;
+use std::env;
+use std::io;
+use std::process;
+use std::time::Duration;
+use structopt::clap::{AppSettings, ArgMatches, ArgSettings};
+use structopt::StructOpt;
+
+mod input;
+mod output;
+
+const EXIT_CODE_SUCCESS: i32 = 0;
+const EXIT_CODE_ERROR: i32 = 1;
+const EXIT_CODE_CANCEL: i32 = 2;
+/// Invalid arguments, matching `EX_USAGE` from sysexits.h
+const EXIT_CODE_USAGE: i32 = 64;
+/// The target app or callback handler could not be reached, matching `EX_UNAVAILABLE`
+const EXIT_CODE_TRANSPORT: i32 = 69;
+/// The target app did not call back in time, matching `timeout(1)`
+const EXIT_CODE_TIMEOUT: i32 = 124;
+
+/// Stands in for the scheme and action to open a complete x-callback-url
+const URL_KEYWORD: &str = "url";
+
+const EXIT_STATUS_HELP: &str = "EXIT STATUS:
+    0      The target app called x-success
+    1      The target app called x-error
+    2      The target app called x-cancel
+    64     Invalid arguments
+    69     The target app or the callback handler could not be reached
+    124    The target app did not call back before --timeout";
+
+#[cfg(all(feature = "macos", target_os = "macos"))]
+fn main() {
+    use callback::macos::{run_app, NSXCallbackClient};
+    use std::thread;
+
+    thread::spawn(move || process::exit(run(NSXCallbackClient::new(), || ())));
+    run_app();
+}
+
+#[cfg(all(feature = "linux", target_os = "linux"))]
+fn main() {
+    use callback::linux::{
+        forward_callback, is_callback_url, register_handler, XdgXCallbackClient,
+    };
+
+    if let Some(url) = env::args().nth(1).filter(|arg| is_callback_url(arg)) {
+        if let Err(e) = forward_callback(&url) {
+            eprintln!("{}", e);
+            process::exit(EXIT_CODE_TRANSPORT);
+        }
+        return;
+    }
+
+    let mut client = XdgXCallbackClient::new();
+    if let Ok(opener) = env::var("CALLBACK_OPENER") {
+        client = client.with_opener(opener.split_whitespace());
+    }
+    process::exit(run(client, || {
+        if env::var_os("CALLBACK_NO_REGISTER").is_some() {
+            return;
+        }
+        if let Err(e) = register_handler() {
+            eprintln!("Unable to register callback handler: {}", e);
+        }
+    }));
+}
+
+#[cfg(not(any(
+    all(feature = "macos", target_os = "macos"),
+    all(feature = "linux", target_os = "linux")
+)))]
+fn main() {
+    eprintln!("callback was built without a backend for this platform");
+    process::exit(1);
+}
+
+#[derive(Debug, StructOpt)]
+/// Interact with x-callback-url APIs
+///
+/// A utility for interacting with local macOS and Linux applications using x-callback-url (http://x-callback-url.com).
+#[structopt(
+    after_help = EXIT_STATUS_HELP,
+    setting = AppSettings::SubcommandsNegateReqs,
+    setting = AppSettings::ArgsNegateSubcommands
+)]
+struct CallbackOpts {
+    #[structopt(subcommand)]
+    command: Option<Command>,
+    #[structopt(flatten)]
+    request: RequestOpts,
+    /// Print the URL that would be opened instead of opening it
+    ///
+    /// The URL includes the x-source, x-success, x-error and x-cancel params callback adds to hear
+    /// back from the target app. Use the build subcommand for the URL without them.
+    #[structopt(long)]
+    dry_run: bool,
+    /// Seconds to wait for a response
+    ///
+    /// Give up waiting for the target app to call back after this many seconds and exit with
+    /// status 124. Waits indefinitely by default.
+    ///
+    /// Example: 2.5
+    #[structopt(long, value_name = "seconds", parse(try_from_str = parse_timeout))]
+    timeout: Option<Duration>,
+    /// Format of the response
+    ///
+    /// text prints the status followed by key=value lines for non-empty params. json prints
+    /// {"status": ..., "params": [{"key": ..., "value": ...}, ...], "request_url": ...} keeping every
+    /// param in order. env prints CALLBACK_STATUS, CALLBACK_REQUEST_URL and CALLBACK_PARAM_<KEY>
+    /// shell assignments for eval. tsv prints the status followed by key<TAB>value lines with tabs,
+    /// newlines and backslashes escaped.
+    #[structopt(
+        long,
+        value_name = "format",
+        default_value = "text",
+        possible_values = OutputFormat::VARIANTS
+    )]
+    output: OutputFormat,
+}
+
+/// The x-callback-url to open
+#[derive(Debug, StructOpt)]
+struct RequestOpts {
+    /// Scheme of target app
+    ///
+    /// Unique string identifier of the target app. To open a complete x-callback-url instead, pass
+    /// it in place of the scheme and action, optionally after `url`. Parameters are added to the
+    /// ones in the URL, with x- parameters replacing any the URL already has. The URL's x-source
+    /// and app specific x- parameters are kept, but its x-success, x-error and x-cancel are
+    /// replaced so callback hears back from the target app.
+    ///
+    /// Example: bear, url "bear://x-callback-url/create?title=My%20Note"
+    #[structopt(set = ArgSettings::Required)]
+    scheme: Option<String>,
+    /// Name of action
+    ///
+    /// Action for target app to execute. Left out when opening a complete x-callback-url.
+    ///
+    /// Example: create
+    action: Option<String>,
+    /// x-callback and action parameters
+    ///
+    /// Space delimited key=value x-callback-url parameters, split on the first `=`. Values are
+    /// encoded for you, unless --encoded is passed. A value of @path reads a text file, @- reads
+    /// stdin and @@path base64 encodes a file, also adding a filename parameter unless one is
+    /// given. Start a value with \@ for a literal @, or pass it with --param or -p.
+    ///
+    /// Example: "title=My Note Title" text=@notes.md 'handle=\@me'
+    #[structopt(parse(try_from_str = parse_parameter))]
+    parameters: Vec<(String, String)>,
+    /// Add a parameter
+    ///
+    /// The value is taken as is and encoded for you, even with --encoded. Values starting with @
+    /// are not read from files.
+    ///
+    /// Example: --param text "a = b"
+    #[structopt(long = "param", number_of_values = 2, value_names = &["key", "value"])]
+    param: Vec<String>,
+    /// Add a key=value parameter
+    ///
+    /// Split on the first `=`. The value is taken as is and encoded for you, even with --encoded.
+    /// Values starting with @ are not read from files.
+    ///
+    /// Example: -p "text=a = b"
+    #[structopt(
+        short = "p",
+        value_name = "key=value",
+        number_of_values = 1,
+        parse(try_from_str = parse_parameter)
+    )]
+    pairs: Vec<(String, String)>,
+    /// Positional parameters are already URL encoded
+    ///
+    /// Decode positional parameters before encoding them again, as in
+    /// title=My%20Note%20Title text=First+line. Values starting with @ are still read from files,
+    /// but values that decode to @... are taken as is.
+    #[structopt(long)]
+    encoded: bool,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Print an x-callback-url without opening it
+    ///
+    /// Takes the same arguments as opening a URL and prints it as is, without the callback params
+    /// callback adds to hear back from the target app. See --dry-run for the URL with them.
+    Build(RequestOpts),
+    /// Decode an x-callback-url without opening it
+    ///
+    /// Prints the scheme, action, action params and callback params of the URL. x-success, x-error
+    /// and x-cancel URLs that are x-callback-urls themselves are decoded too. Exits with status 64
+    /// if the URL is invalid.
+    Parse(ParseOpts),
+}
+
+#[derive(Debug, StructOpt)]
+struct ParseOpts {
+    /// x-callback-url to decode
+    ///
+    /// Example: "bear://x-callback-url/create?title=My%20Note"
+    url: String,
+    /// Also reject duplicate or unknown callback params and invalid callback URLs
+    #[structopt(long)]
+    strict: bool,
+    /// Format of the output
+    ///
+    /// text prints a line per part with params as key=value lines. json prints {"scheme": ...,
+    /// "action": ..., "params": [{"key": ..., "value": ...}, ...], "callback_params": [{"key": ...,
+    /// "value": ..., "url": {...}}, ...]} with "url" only set for decoded callback URLs.
+    #[structopt(
+        long,
+        value_name = "format",
+        default_value = "text",
+        possible_values = OutputFormat::URL_VARIANTS
+    )]
+    output: OutputFormat,
+}
+
+/// Execute the x-callback-url from the command line and return the process exit status
+///
+/// `before_execute` is only called when a URL is about to be opened, not for `parse`, `build`,
+/// `--dry-run`, `--help` or invalid arguments.
+pub fn run<T: XCallbackClient, F: FnOnce()>(client: T, before_execute: F) -> i32 {
+    let matches = match CallbackOpts::clap().get_matches_from_safe(env::args_os()) {
+        Ok(matches) => matches,
+        Err(e) if e.use_stderr() => {
+            eprintln!("{}", e.message);
+            return EXIT_CODE_USAGE;
+        }
+        Err(e) => {
+            println!("{}", e.message);
+            return EXIT_CODE_SUCCESS;
+        }
+    };
+    let opts = CallbackOpts::from_clap(&matches);
+    let execute_url = match (&opts.command, matches.subcommand()) {
+        (Some(Command::Parse(parse_opts)), _) => return parse(parse_opts),
+        (Some(Command::Build(request)), (_, Some(matches))) => return build(request, matches),
+        _ => request_url(&opts.request, &matches),
+    };
+    let execute_url = match execute_url {
+        Ok(url) => url,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_CODE_USAGE;
+        }
+    };
+    if opts.dry_run {
+        println!("{}", client.request_url(&execute_url));
+        return EXIT_CODE_SUCCESS;
+    }
+    before_execute();
+    let result = match opts.timeout {
+        Some(timeout) => client.execute_with_timeout(&execute_url, timeout),
+        None => client.execute(&execute_url),
+    };
+    let response = match result {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("{}", e);
+            return match e {
+                XCallbackError::Timeout => EXIT_CODE_TIMEOUT,
+                _ => EXIT_CODE_TRANSPORT,
+            };
+        }
+    };
+    print!(
+        "{}",
+        format_response(opts.output, &response, &execute_url.to_string())
+    );
+    exit_code(response.status)
+}
+
+fn build(opts: &RequestOpts, matches: &ArgMatches) -> i32 {
+    match request_url(opts, matches) {
+        Ok(url) => {
+            println!("{}", url);
+            EXIT_CODE_SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            EXIT_CODE_USAGE
+        }
+    }
+}
+
+fn parse(opts: &ParseOpts) -> i32 {
+    let result = if opts.strict {
+        XCallbackUrl::parse_strict(&opts.url)
+    } else {
+        XCallbackUrl::parse(&opts.url)
+    };
+    match result {
+        Ok(url) => {
+            print!("{}", format_url(opts.output, &url));
+            EXIT_CODE_SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            EXIT_CODE_USAGE
+        }
+    }
+}
+
+fn exit_code(status: XCallbackStatus) -> i32 {
+    match status {
+        XCallbackStatus::Success => EXIT_CODE_SUCCESS,
+        XCallbackStatus::Error => EXIT_CODE_ERROR,
+        XCallbackStatus::Cancel => EXIT_CODE_CANCEL,
+    }
+}
+
+/// Build the URL described by `opts`, reading any file and stdin parameter values
+///
+/// `matches` are the ones `opts` were parsed from.
+fn request_url(opts: &RequestOpts, matches: &ArgMatches) -> Result<XCallbackUrl, String> {
+    // The scheme is required unless a subcommand is given
+    let scheme = opts.scheme.as_deref().unwrap_or_default();
+
+    if scheme == URL_KEYWORD {
+        let input = opts.action.as_deref().ok_or("Missing URL")?;
+        let parameters = parameters(opts, opts.parameters.clone(), matches)?;
+        return merge_url(input, parameters);
+    }
+    // Schemes can't contain `:`, so this is a complete URL and the action is its first parameter
+    if scheme.contains(':') {
+        let mut positional = opts
+            .action
+            .iter()
+            .map(|action| parse_parameter(action))
+            .collect::<Result<Vec<_>, _>>()?;
+        positional.extend(opts.parameters.iter().cloned());
+        let parameters = parameters(opts, positional, matches)?;
+        return merge_url(scheme, parameters);
+    }
+
+    let parameters = parameters(opts, opts.parameters.clone(), matches)?;
+    XCallbackUrl::builder(scheme)
+        .action(opts.action.as_deref().unwrap_or_default())
+        .params(parameters)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Parse the complete x-callback-url `input` and add `parameters` to it
+///
+/// Action params go after the URL's own, while callback params replace the URL's ones.
+fn merge_url(input: &str, parameters: Vec<(String, String)>) -> Result<XCallbackUrl, String> {
+    let mut url = XCallbackUrl::parse(input).map_err(|e| format!("{}: {}", e, input))?;
+    for (key, value) in parameters {
+        url.add_param(key, value);
+    }
+    Ok(url)
+}
+
+/// `positional` followed by `--param` and `-p` parameters, with positional file and stdin values
+/// read
+///
+/// `matches` are the ones `opts` were parsed from.
+fn parameters(
+    opts: &RequestOpts,
+    positional: Vec<(String, String)>,
+    matches: &ArgMatches,
+) -> Result<Vec<(String, String)>, String> {
+    input::read_values(
+        positional,
+        option_parameters(opts, matches),
+        opts.encoded,
+        io::stdin(),
+    )
+}
+
+/// `--param` and `-p` parameters in the order they were given
+fn option_parameters(opts: &RequestOpts, matches: &ArgMatches) -> Vec<(String, String)> {
+    // Each --param takes two values, so only the index of its key is needed
+    let param_indices = matches.indices_of("param").into_iter().flatten().step_by(2);
+    let params = opts
+        .param
+        .chunks(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()));
+    let pair_indices = matches.indices_of("pairs").into_iter().flatten();
+    let pairs = opts.pairs.iter().cloned();
+
+    let mut parameters: Vec<_> = param_indices
+        .zip(params)
+        .chain(pair_indices.zip(pairs))
+        .collect();
+    parameters.sort_by_key(|(index, _)| *index);
+    parameters.into_iter().map(|(_, pair)| pair).collect()
+}
+
+fn parse_parameter(src: &str) -> Result<(String, String), String> {
+    match src.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err("Invalid parameter format".to_string()),
+    }
+}
+
+fn parse_timeout(src: &str) -> Result<Duration, String> {
+    src.parse()
+        .map_err(|_| "Invalid number of seconds".to_string())
+        .and_then(|secs| Duration::try_from_secs_f64(secs).map_err(|e| e.to_string()))
+}
@@ -0,0 +1,340 @@
+use callback::client::{XCallbackResponse, XCallbackStatus};
+use callback::x_callback_url::*;
+use std::fmt::Write;
+use std::str::FromStr;
+
+const ENV_PREFIX: &str = "CALLBACK_";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Env,
+    Tsv,
+}
+
+impl OutputFormat {
+    pub const VARIANTS: &'static [&'static str] = &["text", "json", "env", "tsv"];
+    /// Formats `format_url` supports
+    pub const URL_VARIANTS: &'static [&'static str] = &["text", "json"];
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "env" => Ok(OutputFormat::Env),
+            "tsv" => Ok(OutputFormat::Tsv),
+            _ => Err(format!("Invalid output format: {}", s)),
+        }
+    }
+}
+
+pub fn format_response(
+    format: OutputFormat,
+    response: &XCallbackResponse,
+    request_url: &str,
+) -> String {
+    match format {
+        OutputFormat::Text => text(response),
+        OutputFormat::Json => json(response, request_url),
+        OutputFormat::Env => env(response, request_url),
+        OutputFormat::Tsv => tsv(response),
+    }
+}
+
+/// Scheme, action, action params and callback params of `url`
+///
+/// `x-success`, `x-error` and `x-cancel` values that are x-callback-urls themselves are decoded
+/// the same way.
+pub fn format_url(format: OutputFormat, url: &XCallbackUrl) -> String {
+    match format {
+        OutputFormat::Json => format!("{}\n", url_json(url)),
+        _ => {
+            let mut output = String::new();
+            url_text(&mut output, url, "");
+            output
+        }
+    }
+}
+
+fn callback_url(key: &str, value: &str) -> Option<XCallbackUrl> {
+    match key {
+        CALLBACK_PARAM_KEY_SUCCESS | CALLBACK_PARAM_KEY_ERROR | CALLBACK_PARAM_KEY_CANCEL => {
+            XCallbackUrl::parse(value).ok()
+        }
+        _ => None,
+    }
+}
+
+fn url_text(output: &mut String, url: &XCallbackUrl, indent: &str) {
+    writeln!(output, "{}scheme: {}", indent, url.scheme()).unwrap();
+    writeln!(output, "{}action: {}", indent, url.action()).unwrap();
+    if !url.action_params().is_empty() {
+        writeln!(output, "{}params:", indent).unwrap();
+        for (k, v) in url.action_params() {
+            writeln!(output, "{}  {}={}", indent, k, v).unwrap();
+        }
+    }
+    let mut callback_params = url.callback_params().iter().peekable();
+    if callback_params.peek().is_some() {
+        writeln!(output, "{}callback params:", indent).unwrap();
+        for (k, v) in callback_params {
+            writeln!(output, "{}  {}={}", indent, k, v).unwrap();
+            if let Some(url) = callback_url(&k, &v) {
+                url_text(output, &url, &format!("{}    ", indent));
+            }
+        }
+    }
+}
+
+fn url_json(url: &XCallbackUrl) -> String {
+    let params: Vec<_> = url
+        .action_params()
+        .iter()
+        .map(|(k, v)| {
+            format!(
+                "{{\"key\": {}, \"value\": {}}}",
+                json_string(&k),
+                json_string(&v)
+            )
+        })
+        .collect();
+    let callback_params: Vec<_> = url
+        .callback_params()
+        .iter()
+        .map(|(k, v)| {
+            let nested = callback_url(&k, &v)
+                .map(|url| format!(", \"url\": {}", url_json(&url)))
+                .unwrap_or_default();
+            format!(
+                "{{\"key\": {}, \"value\": {}{}}}",
+                json_string(&k),
+                json_string(&v),
+                nested
+            )
+        })
+        .collect();
+    format!(
+        "{{\"scheme\": {}, \"action\": {}, \"params\": [{}], \"callback_params\": [{}]}}",
+        json_string(url.scheme()),
+        json_string(url.action()),
+        params.join(", "),
+        callback_params.join(", ")
+    )
+}
+
+fn status(status: XCallbackStatus) -> &'static str {
+    match status {
+        XCallbackStatus::Success => "success",
+        XCallbackStatus::Error => "error",
+        XCallbackStatus::Cancel => "cancel",
+    }
+}
+
+fn text(response: &XCallbackResponse) -> String {
+    let mut output = format!("{}\n", status(response.status));
+    for (k, v) in &response.action_params {
+        if !v.is_empty() {
+            writeln!(output, "{}={}", k, v).unwrap();
+        }
+    }
+    output
+}
+
+fn json(response: &XCallbackResponse, request_url: &str) -> String {
+    let params: Vec<_> = response
+        .action_params
+        .iter()
+        .map(|(k, v)| {
+            format!(
+                "{{\"key\": {}, \"value\": {}}}",
+                json_string(k),
+                json_string(v)
+            )
+        })
+        .collect();
+    format!(
+        "{{\"status\": {}, \"params\": [{}], \"request_url\": {}}}\n",
+        json_string(status(response.status)),
+        params.join(", "),
+        json_string(request_url)
+    )
+}
+
+fn json_string(s: &str) -> String {
+    let mut output = String::with_capacity(s.len() + 2);
+    output.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if c.is_control() => write!(output, "\\u{:04x}", c as u32).unwrap(),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+    output
+}
+
+/// `NAME='value'` lines for `eval`, with params as `CALLBACK_PARAM_<KEY>`
+///
+/// Keys are upper-cased with anything but ASCII letters and digits replaced by `_`, so later
+/// duplicates override earlier ones.
+fn env(response: &XCallbackResponse, request_url: &str) -> String {
+    let mut output = String::new();
+    writeln!(
+        output,
+        "{}STATUS={}",
+        ENV_PREFIX,
+        shell_quote(status(response.status))
+    )
+    .unwrap();
+    writeln!(
+        output,
+        "{}REQUEST_URL={}",
+        ENV_PREFIX,
+        shell_quote(request_url)
+    )
+    .unwrap();
+    for (k, v) in &response.action_params {
+        let name: String = k
+            .chars()
+            .map(|c| match c {
+                c if c.is_ascii_alphanumeric() => c.to_ascii_uppercase(),
+                _ => '_',
+            })
+            .collect();
+        writeln!(output, "{}PARAM_{}={}", ENV_PREFIX, name, shell_quote(v)).unwrap();
+    }
+    output
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Status on the first line followed by a `key<TAB>value` line per param
+///
+/// Tabs, newlines and backslashes are escaped as `\t`, `\n`, `\r` and `\\`.
+fn tsv(response: &XCallbackResponse) -> String {
+    let mut output = format!("{}\n", status(response.status));
+    for (k, v) in &response.action_params {
+        writeln!(output, "{}\t{}", tsv_field(k), tsv_field(v)).unwrap();
+    }
+    output
+}
+
+fn tsv_field(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::output::{format_response, format_url, OutputFormat};
+    use callback::client::{XCallbackResponse, XCallbackStatus};
+    use callback::x_callback_url::XCallbackUrl;
+
+    const REQUEST_URL: &str = "bear://x-callback-url/create?title=Note";
+
+    fn response() -> XCallbackResponse {
+        XCallbackResponse {
+            status: XCallbackStatus::Success,
+            action_params: vec![
+                ("id".to_string(), "1".to_string()),
+                ("note".to_string(), "".to_string()),
+                ("id".to_string(), "say \"hi\"\n\tit's".to_string()),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(
+            format_response(OutputFormat::Text, &response(), REQUEST_URL),
+            "success\nid=1\nid=say \"hi\"\n\tit's\n"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        assert_eq!(
+            format_response(OutputFormat::Json, &response(), REQUEST_URL),
+            "{\"status\": \"success\", \"params\": [\
+             {\"key\": \"id\", \"value\": \"1\"}, \
+             {\"key\": \"note\", \"value\": \"\"}, \
+             {\"key\": \"id\", \"value\": \"say \\\"hi\\\"\\n\\tit's\"}], \
+             \"request_url\": \"bear://x-callback-url/create?title=Note\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_env() {
+        assert_eq!(
+            format_response(OutputFormat::Env, &response(), REQUEST_URL),
+            "CALLBACK_STATUS='success'\n\
+             CALLBACK_REQUEST_URL='bear://x-callback-url/create?title=Note'\n\
+             CALLBACK_PARAM_ID='1'\n\
+             CALLBACK_PARAM_NOTE=''\n\
+             CALLBACK_PARAM_ID='say \"hi\"\n\tit'\\''s'\n"
+        );
+    }
+
+    #[test]
+    fn test_tsv() {
+        assert_eq!(
+            format_response(OutputFormat::Tsv, &response(), REQUEST_URL),
+            "success\nid\t1\nnote\t\nid\tsay \"hi\"\\n\\tit's\n"
+        );
+    }
+
+    fn url() -> XCallbackUrl {
+        XCallbackUrl::parse(
+            "bear://x-callback-url/create?title=My+Note&x-source=app\
+             &x-success=callback%3A%2F%2Fx-callback-url%2Fsuccess%3Fcallback_id%3Dabc\
+             &x-error=https%3A%2F%2Fexample.com",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_url_text() {
+        assert_eq!(
+            format_url(OutputFormat::Text, &url()),
+            "scheme: bear\n\
+             action: create\n\
+             params:\n  title=My Note\n\
+             callback params:\n\
+             \x20 x-source=app\n\
+             \x20 x-success=callback://x-callback-url/success?callback_id=abc\n\
+             \x20   scheme: callback\n\
+             \x20   action: success\n\
+             \x20   params:\n\
+             \x20     callback_id=abc\n\
+             \x20 x-error=https://example.com\n"
+        );
+    }
+
+    #[test]
+    fn test_url_json() {
+        assert_eq!(
+            format_url(OutputFormat::Json, &url()),
+            "{\"scheme\": \"bear\", \"action\": \"create\", \
+             \"params\": [{\"key\": \"title\", \"value\": \"My Note\"}], \"callback_params\": [\
+             {\"key\": \"x-source\", \"value\": \"app\"}, \
+             {\"key\": \"x-success\", \"value\": \"callback://x-callback-url/success?callback_id=abc\", \
+             \"url\": {\"scheme\": \"callback\", \"action\": \"success\", \
+             \"params\": [{\"key\": \"callback_id\", \"value\": \"abc\"}], \"callback_params\": []}}, \
+             {\"key\": \"x-error\", \"value\": \"https://example.com\"}]}\n"
+        );
+    }
+}
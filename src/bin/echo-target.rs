@@ -0,0 +1,124 @@
+use callback::x_callback_url::*;
+use std::process::{self, Command};
+use std::thread;
+use std::time::Duration;
+use structopt::StructOpt;
+use url::Url;
+
+const ECHO_TARGET_SCHEME: &str = "echo-target";
+const ACTION_ECHO: &str = "echo";
+const ACTION_FAIL: &str = "fail";
+const ACTION_CANCEL: &str = "cancel";
+const ACTION_DELAY: &str = "delay";
+const DEFAULT_DELAY: Duration = Duration::from_secs(1);
+
+#[cfg(target_os = "macos")]
+const DEFAULT_OPENER: &str = "open";
+#[cfg(not(target_os = "macos"))]
+const DEFAULT_OPENER: &str = "xdg-open";
+
+#[derive(Debug, StructOpt)]
+/// Loopback x-callback-url target app for end-to-end testing
+///
+/// Handles echo-target://x-callback-url/<action> URLs by calling back the x-success, x-error or
+/// x-cancel URL depending on the action:
+///
+/// echo    calls x-success with the action parameters
+///
+/// fail    calls x-error with errorCode and errorMessage (from the code and message parameters)
+///
+/// cancel  calls x-cancel
+///
+/// delay   waits for the seconds parameter (default 1) and then behaves like echo
+struct EchoTargetOpts {
+    /// Command used to open callback URLs
+    #[structopt(long, default_value = DEFAULT_OPENER)]
+    opener: String,
+    /// Register echo-target as the handler for echo-target:// URLs and exit (Linux only)
+    #[structopt(long)]
+    register: bool,
+    /// x-callback-url to handle
+    #[structopt(required_unless = "register")]
+    url: Option<String>,
+}
+
+fn main() {
+    let opts = EchoTargetOpts::from_args();
+
+    if opts.register {
+        register();
+        return;
+    }
+
+    let url = XCallbackUrl::parse(opts.url.as_deref().unwrap_or_default()).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    if let Some(callback_url) = respond(&url) {
+        let status = Command::new(&opts.opener)
+            .arg(callback_url.as_str())
+            .status()
+            .unwrap();
+        process::exit(status.code().unwrap_or(1));
+    }
+}
+
+#[cfg(all(feature = "linux", target_os = "linux"))]
+fn register() {
+    callback::linux::register_scheme_handler(ECHO_TARGET_SCHEME).unwrap();
+}
+
+#[cfg(not(all(feature = "linux", target_os = "linux")))]
+fn register() {
+    eprintln!(
+        "Registering a handler for {}:// URLs is only supported on Linux",
+        ECHO_TARGET_SCHEME
+    );
+    process::exit(1);
+}
+
+fn respond(url: &XCallbackUrl) -> Option<Url> {
+    let callback_params = url.callback_params();
+    let action_params = url.action_params();
+
+    match url.action() {
+        ACTION_ECHO => callback(callback_params.success(), action_params.iter()),
+        ACTION_FAIL => {
+            let code = find_param(url, "code").unwrap_or_else(|| "1".to_string());
+            let message = find_param(url, "message").unwrap_or_else(|| "fail".to_string());
+            callback(
+                callback_params.error(),
+                vec![("errorCode", code), ("errorMessage", message)],
+            )
+        }
+        ACTION_CANCEL => callback(callback_params.cancel(), Vec::<(&str, &str)>::new()),
+        ACTION_DELAY => {
+            let delay = find_param(url, "seconds")
+                .and_then(|s| s.parse().ok())
+                .and_then(|s| Duration::try_from_secs_f64(s).ok())
+                .unwrap_or(DEFAULT_DELAY);
+            thread::sleep(delay);
+            callback(callback_params.success(), action_params.iter())
+        }
+        action => callback(
+            callback_params.error(),
+            vec![("errorMessage", format!("Unknown action: {}", action))],
+        ),
+    }
+}
+
+fn find_param(url: &XCallbackUrl, key: &str) -> Option<String> {
+    url.action_params().get(key).map(str::to_string)
+}
+
+fn callback<I, K, V>(callback_url: Option<&str>, params: I) -> Option<Url>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    let mut callback_url = Url::parse(callback_url?).ok()?;
+    callback_url.query_pairs_mut().extend_pairs(params);
+    Some(callback_url)
+}
@@ -0,0 +1,237 @@
+// Only callback ids need a backend, the rest is built for tests on any platform too
+#![cfg_attr(
+    not(any(
+        all(feature = "macos", target_os = "macos"),
+        all(feature = "linux", target_os = "linux")
+    )),
+    allow(dead_code)
+)]
+
+use crate::client::{XCallbackResponse, XCallbackStatus};
+use crate::x_callback_url::*;
+#[cfg(any(
+    all(feature = "macos", target_os = "macos"),
+    all(feature = "linux", target_os = "linux")
+))]
+use rand::distributions::Alphanumeric;
+#[cfg(any(
+    all(feature = "macos", target_os = "macos"),
+    all(feature = "linux", target_os = "linux")
+))]
+use rand::{thread_rng, Rng};
+use url::Url;
+
+pub const CALLBACK_SCHEME: &str = "callback";
+const CALLBACK_SOURCE: &str = "callback";
+const CALLBACK_ACTION_SUCCESS: &str = "success";
+const CALLBACK_ACTION_ERROR: &str = "error";
+const CALLBACK_ACTION_CANCEL: &str = "cancel";
+const CALLBACK_PARAM_KEY_CALLBACK_ID: &str = "callback_id";
+
+#[cfg(any(
+    all(feature = "macos", target_os = "macos"),
+    all(feature = "linux", target_os = "linux")
+))]
+pub fn generate_callback_id() -> String {
+    thread_rng().sample_iter(&Alphanumeric).take(32).collect()
+}
+
+pub fn generate_callback_url(url: &XCallbackUrl, callback_id: &str) -> XCallbackUrl {
+    fn generate_callback_url(action: &str, callback_id: &str) -> String {
+        let mut url = XCallbackUrl::new(CALLBACK_SCHEME);
+        url.set_action(action);
+        url.action_params_mut()
+            .push(CALLBACK_PARAM_KEY_CALLBACK_ID, callback_id);
+        url.to_string()
+    }
+
+    let mut callback_url = url.clone();
+    // The target app only shows the source to the user, so one that is already set can stay
+    if callback_url.callback_params().source().is_none() {
+        callback_url
+            .callback_params_mut()
+            .set_source(Some(CALLBACK_SOURCE));
+    }
+    callback_url
+        .callback_params_mut()
+        .set_success(Some(generate_callback_url(
+            CALLBACK_ACTION_SUCCESS,
+            callback_id,
+        )));
+    callback_url
+        .callback_params_mut()
+        .set_error(Some(generate_callback_url(
+            CALLBACK_ACTION_ERROR,
+            callback_id,
+        )));
+    callback_url
+        .callback_params_mut()
+        .set_cancel(Some(generate_callback_url(
+            CALLBACK_ACTION_CANCEL,
+            callback_id,
+        )));
+    callback_url
+}
+
+/// Where an incoming callback URL should be handed to
+// Routes are matched on right away, so boxing the callback would only add an allocation
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum Route {
+    /// Deliver the callback, or why it is malformed, to the request waiting on `callback_id`
+    Deliver {
+        callback_id: String,
+        callback: Result<XCallbackUrl, XCallbackError>,
+    },
+    /// No request can be told about the callback, so it should be logged and dropped
+    Ignore(XCallbackError),
+}
+
+/// Decide what to do with a URL the callback handler was opened with
+///
+/// Callbacks without a `callback_id` cannot be matched to a request and are ignored. Callbacks
+/// that have one but are otherwise malformed are still delivered, as a
+/// `XCallbackError::MalformedCallback`, so the waiting request fails instead of hanging.
+pub fn route_callback(input: Option<&str>) -> Route {
+    let input = match input {
+        Some(input) => input,
+        None => return Route::Ignore(XCallbackError::MalformedCallback("Missing URL".to_string())),
+    };
+    let callback_id = match Url::parse(input) {
+        Ok(url) => url
+            .query_pairs()
+            .find(|(k, _)| k == CALLBACK_PARAM_KEY_CALLBACK_ID)
+            .map(|(_, v)| v.to_string()),
+        Err(e) => {
+            return Route::Ignore(XCallbackError::MalformedCallback(format!(
+                "{}: {}",
+                e, input
+            )))
+        }
+    };
+    let callback_id = match callback_id {
+        Some(callback_id) => callback_id,
+        None => {
+            return Route::Ignore(XCallbackError::MalformedCallback(format!(
+                "Missing callback id: {}",
+                input
+            )))
+        }
+    };
+
+    let callback = XCallbackUrl::parse(input)
+        .map_err(|e| e.to_string())
+        .and_then(|url| match url.action() {
+            CALLBACK_ACTION_SUCCESS | CALLBACK_ACTION_ERROR | CALLBACK_ACTION_CANCEL => Ok(url),
+            action => Err(format!("Invalid action: {}", action)),
+        })
+        .map_err(XCallbackError::MalformedCallback);
+
+    Route::Deliver {
+        callback_id,
+        callback,
+    }
+}
+
+pub fn callback_url_to_response(
+    callback_url: XCallbackUrl,
+) -> Result<XCallbackResponse, XCallbackError> {
+    let status = match callback_url.action() {
+        CALLBACK_ACTION_SUCCESS => XCallbackStatus::Success,
+        CALLBACK_ACTION_ERROR => XCallbackStatus::Error,
+        CALLBACK_ACTION_CANCEL => XCallbackStatus::Cancel,
+        action => return Err(XCallbackError::InvalidAction(action.to_string())),
+    };
+    let mut action_params = callback_url.action_params().clone();
+    action_params.remove(CALLBACK_PARAM_KEY_CALLBACK_ID);
+    let action_params = action_params.into_iter().collect();
+
+    Ok(XCallbackResponse {
+        status,
+        action_params,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::callback_url::{generate_callback_url, route_callback, Route};
+    use crate::x_callback_url::{XCallbackError, XCallbackUrl};
+
+    fn assert_malformed(result: &Result<XCallbackUrl, XCallbackError>) {
+        assert!(
+            matches!(result, Err(XCallbackError::MalformedCallback(_))),
+            "expected a malformed callback, got {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_generate_callback_url_keeps_source() {
+        let mut url = XCallbackUrl::new("bear");
+        assert_eq!(
+            generate_callback_url(&url, "abc")
+                .callback_params()
+                .source(),
+            Some("callback")
+        );
+
+        url.callback_params_mut().set_source(Some("app"));
+        assert_eq!(
+            generate_callback_url(&url, "abc")
+                .callback_params()
+                .source(),
+            Some("app")
+        );
+    }
+
+    #[test]
+    fn test_route_callback() {
+        let url = generate_callback_url(&XCallbackUrl::new("bear"), "abc");
+        let success = url.callback_params().success().unwrap();
+
+        match route_callback(Some(success)) {
+            Route::Deliver {
+                callback_id,
+                callback,
+            } => {
+                assert_eq!(callback_id, "abc");
+                assert_eq!(callback.unwrap().action(), "success");
+            }
+            route => panic!("expected a delivery, got {:?}", route),
+        }
+    }
+
+    #[test]
+    fn test_route_callback_malformed() {
+        for input in &[
+            "callback://x-callback-url/unknown?callback_id=abc",
+            "callback://elsewhere/success?callback_id=abc",
+        ] {
+            match route_callback(Some(input)) {
+                Route::Deliver {
+                    callback_id,
+                    callback,
+                } => {
+                    assert_eq!(callback_id, "abc");
+                    assert_malformed(&callback);
+                }
+                route => panic!("expected a delivery, got {:?}", route),
+            }
+        }
+    }
+
+    #[test]
+    fn test_route_callback_ignored() {
+        for input in &[
+            None,
+            Some("not a url"),
+            Some("callback://x-callback-url/success"),
+            Some("callback://x-callback-url/success?errorMessage=callback_id"),
+        ] {
+            match route_callback(*input) {
+                Route::Ignore(error) => assert_malformed(&Err(error)),
+                route => panic!("expected {:?} to be ignored, got {:?}", input, route),
+            }
+        }
+    }
+}
@@ -1,11 +1,56 @@
-use crate::x_callback_url::XCallbackUrl;
-use std::error::Error;
+use crate::x_callback_url::{
+    self, ActionParams, FromCallbackParams, ParamValue, XCallbackError, XCallbackUrl,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::str::FromStr;
+use std::time::Duration;
 
+pub mod mock;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct XCallbackResponse {
     pub status: XCallbackStatus,
     pub action_params: Vec<(String, String)>,
 }
 
+impl XCallbackResponse {
+    /// Value of the first action param with `key`
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.action_params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Read the action params as a `T`
+    pub fn params_as<T: FromCallbackParams>(&self) -> Result<T, XCallbackError> {
+        let mut params = ActionParams::default();
+        params.extend(self.action_params.iter().cloned());
+        T::from_callback_params(&params)
+    }
+
+    /// Value of the first action param with `key` read as a `T`
+    pub fn get_as<T: ParamValue>(&self, key: &str) -> Result<Option<T>, XCallbackError> {
+        self.get(key)
+            .map(|value| x_callback_url::param_value(key, value))
+            .transpose()
+    }
+
+    /// Value of the first action param with `key` parsed with `T::from_str`
+    pub fn get_parsed<T: FromStr>(&self, key: &str) -> Result<Option<T>, XCallbackError> {
+        self.get(key)
+            .map(|value| x_callback_url::parsed_value(key, value))
+            .transpose()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum XCallbackStatus {
     Success,
     Error,
@@ -13,5 +58,32 @@ pub enum XCallbackStatus {
 }
 
 pub trait XCallbackClient {
-    fn execute(&self, url: &XCallbackUrl) -> Result<XCallbackResponse, Box<dyn Error>>;
+    fn execute(&self, url: &XCallbackUrl) -> Result<XCallbackResponse, XCallbackError>;
+
+    /// Like `execute`, but gives up with `XCallbackError::Timeout` if the target app has not
+    /// called back within `timeout`
+    fn execute_with_timeout(
+        &self,
+        url: &XCallbackUrl,
+        timeout: Duration,
+    ) -> Result<XCallbackResponse, XCallbackError>;
+
+    /// The URL `execute` opens for `url`
+    ///
+    /// Clients that wait for the target app to call back add their own `x-source`, `x-success`,
+    /// `x-error` and `x-cancel` params to it.
+    fn request_url(&self, url: &XCallbackUrl) -> XCallbackUrl {
+        url.clone()
+    }
+}
+
+/// Non-blocking counterpart of `XCallbackClient`
+///
+/// Every call to `execute` is tracked on its own, so many requests can be in flight at once.
+/// Dropping the returned future stops waiting for the response.
+#[cfg(feature = "async")]
+pub trait AsyncXCallbackClient {
+    type Future: Future<Output = Result<XCallbackResponse, XCallbackError>>;
+
+    fn execute(&self, url: &XCallbackUrl) -> Self::Future;
 }
@@ -0,0 +1,361 @@
+//! In-process `XCallbackClient` for testing code that consumes `XCallbackResponse`s
+//!
+//! ```
+//! use callback::client::mock::{Expectation, MockXCallbackClient};
+//! use callback::client::{XCallbackClient, XCallbackStatus};
+//! use callback::x_callback_url::XCallbackUrl;
+//!
+//! let client = MockXCallbackClient::new();
+//! client
+//!     .expect(Expectation::new().scheme("bear").action("create").success(&[("identifier", "1")]))
+//!     .expect(Expectation::new().action("open-note").cancel(&[]));
+//!
+//! let mut url = XCallbackUrl::new("bear");
+//! url.set_action("create");
+//! let response = client.execute(&url).unwrap();
+//!
+//! assert_eq!(response.status, XCallbackStatus::Success);
+//! assert_eq!(response.action_params, vec![("identifier".to_string(), "1".to_string())]);
+//! client.assert_calls(&[("bear", "create")]);
+//! ```
+
+#[cfg(feature = "async")]
+use crate::client::AsyncXCallbackClient;
+use crate::client::{XCallbackClient, XCallbackResponse, XCallbackStatus};
+use crate::x_callback_url::{XCallbackError, XCallbackUrl};
+#[cfg(feature = "async")]
+use std::future::{self, Ready};
+use std::io;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Reply {
+    Response(XCallbackResponse),
+    Timeout,
+}
+
+/// Scripted reply to the calls that match it
+///
+/// Without any matchers an expectation matches every call, and without a reply it succeeds with
+/// no params.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expectation {
+    scheme: Option<String>,
+    action: Option<String>,
+    params: Vec<(String, String)>,
+    reply: Reply,
+    times: Option<usize>,
+}
+
+impl Expectation {
+    pub fn new() -> Expectation {
+        Expectation {
+            scheme: None,
+            action: None,
+            params: vec![],
+            reply: Reply::Response(XCallbackResponse {
+                status: XCallbackStatus::Success,
+                action_params: vec![],
+            }),
+            times: None,
+        }
+    }
+
+    /// Only match calls to `scheme`
+    pub fn scheme<T: ToString>(mut self, scheme: T) -> Self {
+        self.scheme = Some(scheme.to_string());
+        self
+    }
+
+    /// Only match calls to `action`
+    pub fn action<T: ToString>(mut self, action: T) -> Self {
+        self.action = Some(action.to_string());
+        self
+    }
+
+    /// Only match calls with an action param `key` equal to `value`
+    pub fn param<K: ToString, V: ToString>(mut self, key: K, value: V) -> Self {
+        self.params.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Only match the next `times` calls, after which the expectation is used up
+    pub fn times(mut self, times: usize) -> Self {
+        self.times = Some(times);
+        self
+    }
+
+    pub fn success(self, action_params: &[(&str, &str)]) -> Self {
+        self.respond(XCallbackStatus::Success, action_params)
+    }
+
+    pub fn error(self, action_params: &[(&str, &str)]) -> Self {
+        self.respond(XCallbackStatus::Error, action_params)
+    }
+
+    pub fn cancel(self, action_params: &[(&str, &str)]) -> Self {
+        self.respond(XCallbackStatus::Cancel, action_params)
+    }
+
+    /// Reply as if the target app never called back
+    pub fn timeout(mut self) -> Self {
+        self.reply = Reply::Timeout;
+        self
+    }
+
+    fn respond(mut self, status: XCallbackStatus, action_params: &[(&str, &str)]) -> Self {
+        let action_params = action_params
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        self.reply = Reply::Response(XCallbackResponse {
+            status,
+            action_params,
+        });
+        self
+    }
+
+    fn matches(&self, url: &XCallbackUrl) -> bool {
+        self.scheme.iter().all(|s| s == url.scheme())
+            && self.action.iter().all(|a| a == url.action())
+            && self
+                .params
+                .iter()
+                .all(|(key, value)| url.action_params().get_all(key).any(|v| v == value))
+    }
+
+    fn is_used_up(&self) -> bool {
+        self.times == Some(0)
+    }
+}
+
+impl Default for Expectation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `XCallbackClient` that replies from scripted `Expectation`s instead of opening URLs
+///
+/// Expectations are tried in the order they were added and the first one matching a call
+/// replies to it. Calls that match no expectation fail.
+#[derive(Debug, Default)]
+pub struct MockXCallbackClient {
+    expectations: Mutex<Vec<Expectation>>,
+    calls: Mutex<Vec<XCallbackUrl>>,
+}
+
+impl MockXCallbackClient {
+    pub fn new() -> MockXCallbackClient {
+        Default::default()
+    }
+
+    pub fn expect(&self, expectation: Expectation) -> &Self {
+        self.expectations.lock().unwrap().push(expectation);
+        self
+    }
+
+    /// Every URL executed so far, oldest first
+    pub fn calls(&self) -> Vec<XCallbackUrl> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Assert the `(scheme, action)` of every call made so far, in order
+    pub fn assert_calls(&self, expected: &[(&str, &str)]) {
+        let calls = self.calls.lock().unwrap();
+        let actual: Vec<_> = calls.iter().map(|c| (c.scheme(), c.action())).collect();
+        assert_eq!(actual, expected, "unexpected x-callback-url calls");
+    }
+
+    /// Assert that calls to each `(scheme, action)` were made in this order, allowing other calls
+    /// in between
+    pub fn assert_called_in_order(&self, expected: &[(&str, &str)]) {
+        let calls = self.calls.lock().unwrap();
+        let mut actual = calls.iter().map(|c| (c.scheme(), c.action()));
+        for (scheme, action) in expected {
+            assert!(
+                actual.any(|call| call == (scheme, action)),
+                "expected a call to {}://x-callback-url/{} in order, got {:?}",
+                scheme,
+                action,
+                calls
+                    .iter()
+                    .map(|c| (c.scheme(), c.action()))
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+
+    /// Assert that every expectation limited with `Expectation::times` was used up
+    pub fn assert_expectations_met(&self) {
+        let expectations = self.expectations.lock().unwrap();
+        let unmet: Vec<_> = expectations
+            .iter()
+            .filter(|e| e.times.is_some() && !e.is_used_up())
+            .collect();
+        assert!(unmet.is_empty(), "unmet expectations: {:?}", unmet);
+    }
+
+    fn reply(&self, url: &XCallbackUrl) -> Result<XCallbackResponse, XCallbackError> {
+        self.calls.lock().unwrap().push(url.clone());
+
+        let mut expectations = self.expectations.lock().unwrap();
+        let expectation = expectations
+            .iter_mut()
+            .find(|e| !e.is_used_up() && e.matches(url))
+            .ok_or_else(|| {
+                XCallbackError::Transport(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Unexpected x-callback-url: {}", url),
+                ))
+            })?;
+        if let Some(times) = expectation.times.as_mut() {
+            *times -= 1;
+        }
+
+        match &expectation.reply {
+            Reply::Response(response) => Ok(response.clone()),
+            Reply::Timeout => Err(XCallbackError::Timeout),
+        }
+    }
+}
+
+impl XCallbackClient for MockXCallbackClient {
+    fn execute(&self, url: &XCallbackUrl) -> Result<XCallbackResponse, XCallbackError> {
+        self.reply(url)
+    }
+
+    fn execute_with_timeout(
+        &self,
+        url: &XCallbackUrl,
+        _timeout: Duration,
+    ) -> Result<XCallbackResponse, XCallbackError> {
+        self.reply(url)
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncXCallbackClient for MockXCallbackClient {
+    type Future = Ready<Result<XCallbackResponse, XCallbackError>>;
+
+    fn execute(&self, url: &XCallbackUrl) -> Self::Future {
+        future::ready(self.reply(url))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::client::mock::{Expectation, MockXCallbackClient};
+    use crate::client::{XCallbackClient, XCallbackStatus};
+    use crate::x_callback_url::{XCallbackError, XCallbackUrl};
+
+    fn url(scheme: &str, action: &str, params: &[(&str, &str)]) -> XCallbackUrl {
+        let mut url = XCallbackUrl::new(scheme);
+        url.set_action(action);
+        url.action_params_mut().append(params);
+        url
+    }
+
+    #[test]
+    fn test_first_match_replies() {
+        let client = MockXCallbackClient::new();
+        client
+            .expect(
+                Expectation::new()
+                    .action("create")
+                    .param("title", "Draft")
+                    .error(&[("errorMessage", "exists")]),
+            )
+            .expect(Expectation::new().action("create").success(&[("id", "1")]));
+
+        let draft = client
+            .execute(&url("bear", "create", &[("title", "Draft")]))
+            .unwrap();
+        let other = client
+            .execute(&url("bear", "create", &[("title", "Other")]))
+            .unwrap();
+
+        assert_eq!(draft.status, XCallbackStatus::Error);
+        assert_eq!(
+            draft.action_params,
+            vec![("errorMessage".to_string(), "exists".to_string())]
+        );
+        assert_eq!(other.status, XCallbackStatus::Success);
+        assert_eq!(
+            other.action_params,
+            vec![("id".to_string(), "1".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_times() {
+        let client = MockXCallbackClient::new();
+        client
+            .expect(Expectation::new().times(1).cancel(&[]))
+            .expect(Expectation::new());
+
+        let first = client.execute(&url("bear", "create", &[])).unwrap();
+        let second = client.execute(&url("bear", "create", &[])).unwrap();
+
+        assert_eq!(first.status, XCallbackStatus::Cancel);
+        assert_eq!(second.status, XCallbackStatus::Success);
+        client.assert_expectations_met();
+    }
+
+    #[test]
+    #[should_panic(expected = "unmet expectations")]
+    fn test_unmet_expectations() {
+        let client = MockXCallbackClient::new();
+        client.expect(Expectation::new().times(2));
+
+        client.execute(&url("bear", "create", &[])).unwrap();
+
+        client.assert_expectations_met();
+    }
+
+    #[test]
+    fn test_unexpected_call() {
+        let client = MockXCallbackClient::new();
+        client.expect(Expectation::new().scheme("things"));
+
+        assert!(client.execute(&url("bear", "create", &[])).is_err());
+        client.assert_calls(&[("bear", "create")]);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let client = MockXCallbackClient::new();
+        client.expect(Expectation::new().timeout());
+
+        let error = client.execute(&url("bear", "create", &[])).unwrap_err();
+
+        assert!(matches!(error, XCallbackError::Timeout));
+    }
+
+    #[test]
+    fn test_call_order() {
+        let client = MockXCallbackClient::new();
+        client.expect(Expectation::new());
+
+        client.execute(&url("bear", "create", &[])).unwrap();
+        client.execute(&url("things", "add", &[])).unwrap();
+        client.execute(&url("bear", "open-note", &[])).unwrap();
+
+        client.assert_calls(&[("bear", "create"), ("things", "add"), ("bear", "open-note")]);
+        client.assert_called_in_order(&[("bear", "create"), ("bear", "open-note")]);
+        assert_eq!(client.calls()[1].scheme(), "things");
+    }
+
+    #[test]
+    #[should_panic(expected = "in order")]
+    fn test_call_order_mismatch() {
+        let client = MockXCallbackClient::new();
+        client.expect(Expectation::new());
+
+        client.execute(&url("bear", "create", &[])).unwrap();
+        client.execute(&url("bear", "open-note", &[])).unwrap();
+
+        client.assert_called_in_order(&[("bear", "open-note"), ("bear", "create")]);
+    }
+}
@@ -1,9 +1,22 @@
+#[cfg(all(feature = "macos", target_os = "macos"))]
 #[macro_use]
 extern crate lazy_static;
 
+#[cfg(all(feature = "macos", target_os = "macos"))]
 #[macro_use]
 extern crate objc;
 
+#[cfg(any(
+    test,
+    all(feature = "macos", target_os = "macos"),
+    all(feature = "linux", target_os = "linux")
+))]
+mod callback_url;
 pub mod client;
+#[cfg(all(feature = "linux", target_os = "linux"))]
+pub mod linux;
+#[cfg(all(feature = "macos", target_os = "macos"))]
 pub mod macos;
+#[cfg(any(test, all(feature = "macos", target_os = "macos")))]
+mod pending;
 pub mod x_callback_url;
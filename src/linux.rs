@@ -0,0 +1,361 @@
+use crate::callback_url::{
+    self, generate_callback_id, generate_callback_url, Route, CALLBACK_SCHEME,
+};
+#[cfg(feature = "async")]
+use crate::client::AsyncXCallbackClient;
+use crate::client::{XCallbackClient, XCallbackResponse};
+use crate::x_callback_url::*;
+#[cfg(feature = "async")]
+use futures_channel::oneshot;
+use std::env;
+use std::fs;
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "async")]
+use std::pin::Pin;
+use std::process::Command;
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_OPENER: &str = "xdg-open";
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Register the current executable as the handler for `callback://` URLs
+///
+/// The handler is invoked with the callback URL as its only argument and is expected to pass it
+/// on to [`forward_callback`].
+pub fn register_handler() -> Result<(), XCallbackError> {
+    register_scheme_handler(CALLBACK_SCHEME)
+}
+
+/// Register the current executable as the handler for `scheme://` URLs
+///
+/// Writes a `<scheme>.desktop` file to the user's applications directory and makes it the default
+/// `x-scheme-handler/<scheme>` handler with `xdg-mime`.
+pub fn register_scheme_handler(scheme: &str) -> Result<(), XCallbackError> {
+    let exe = env::current_exe()?;
+    let applications_dir = applications_dir()?;
+    let desktop_file_name = format!("{}.desktop", scheme);
+    let desktop_file = applications_dir.join(&desktop_file_name);
+    let desktop_entry = desktop_entry(&exe, scheme);
+
+    if fs::read_to_string(&desktop_file).ok().as_deref() == Some(desktop_entry.as_str()) {
+        return Ok(());
+    }
+
+    fs::create_dir_all(&applications_dir)?;
+    fs::write(&desktop_file, desktop_entry)?;
+
+    let mime_type = format!("x-scheme-handler/{}", scheme);
+    let result = Command::new("xdg-mime")
+        .args(["default", &desktop_file_name, &mime_type])
+        .status()
+        .and_then(|status| {
+            if status.success() {
+                Ok(())
+            } else {
+                Err(io::Error::other(format!("xdg-mime exited with {}", status)))
+            }
+        });
+
+    if let Err(e) = result {
+        // Leave nothing behind so registration is retried on the next run
+        let _ = fs::remove_file(&desktop_file);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+fn applications_dir() -> Result<PathBuf, XCallbackError> {
+    let data_home = match env::var_os("XDG_DATA_HOME") {
+        Some(data_home) => PathBuf::from(data_home),
+        None => {
+            let home = env::var_os("HOME")
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?;
+            PathBuf::from(home).join(".local").join("share")
+        }
+    };
+    Ok(data_home.join("applications"))
+}
+
+fn desktop_entry(exe: &Path, scheme: &str) -> String {
+    format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name={scheme}\n\
+         Exec=\"{exe}\" %u\n\
+         NoDisplay=true\n\
+         Terminal=false\n\
+         MimeType=x-scheme-handler/{scheme};\n",
+        exe = exe.display(),
+        scheme = scheme,
+    )
+}
+
+/// Whether `input` is a URL addressed to the registered `callback://` handler
+pub fn is_callback_url(input: &str) -> bool {
+    input.starts_with(&format!("{}://", CALLBACK_SCHEME))
+}
+
+/// Deliver a callback URL received by the `callback://` handler to the waiting client
+///
+/// Malformed callbacks with a callback id are delivered too, so the client can fail instead of
+/// waiting for a callback that never comes. Those without one are returned as errors.
+pub fn forward_callback(input: &str) -> Result<(), XCallbackError> {
+    match callback_url::route_callback(Some(input)) {
+        Route::Deliver { callback_id, .. } => {
+            let mut stream = UnixStream::connect(socket_path(&callback_id))?;
+            stream.write_all(input.as_bytes())?;
+            Ok(())
+        }
+        Route::Ignore(e) => Err(e),
+    }
+}
+
+fn socket_path(callback_id: &str) -> PathBuf {
+    env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir)
+        .join(format!("{}-{}.sock", CALLBACK_SCHEME, callback_id))
+}
+
+/// Run the opener for `url` and wait for it to exit, giving up at `deadline`
+///
+/// Openers like `xdg-open` can run the target app's handler in the foreground, so waiting for them
+/// counts against the timeout too.
+fn open(
+    opener: &[String],
+    url: &XCallbackUrl,
+    deadline: Option<Instant>,
+) -> Result<(), XCallbackError> {
+    let mut child = Command::new(&opener[0])
+        .args(&opener[1..])
+        .arg(url.to_url()?.as_str())
+        .spawn()?;
+    let status = match deadline {
+        Some(deadline) => loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(XCallbackError::Timeout);
+            }
+            thread::sleep(ACCEPT_POLL_INTERVAL);
+        },
+        None => child.wait()?,
+    };
+    if !status.success() {
+        return Err(XCallbackError::Transport(io::Error::other(format!(
+            "{} exited with {}",
+            opener[0], status
+        ))));
+    }
+    Ok(())
+}
+
+struct CallbackListener {
+    path: PathBuf,
+    listener: UnixListener,
+}
+
+impl CallbackListener {
+    fn bind(callback_id: &str) -> io::Result<CallbackListener> {
+        let path = socket_path(callback_id);
+        let listener = UnixListener::bind(&path)?;
+        Ok(CallbackListener { path, listener })
+    }
+
+    fn receive(&self, deadline: Option<Instant>) -> Result<XCallbackUrl, XCallbackError> {
+        let mut stream = match deadline {
+            Some(deadline) => {
+                let stream = self.accept_before(deadline)?;
+                stream.set_read_timeout(Some(
+                    deadline.saturating_duration_since(Instant::now()) + ACCEPT_POLL_INTERVAL,
+                ))?;
+                stream
+            }
+            None => self.listener.accept()?.0,
+        };
+
+        let mut input = String::new();
+        match stream.read_to_string(&mut input) {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                return Err(XCallbackError::Timeout)
+            }
+            result => result?,
+        };
+        match callback_url::route_callback(Some(&input)) {
+            Route::Deliver { callback, .. } => callback,
+            Route::Ignore(e) => Err(e),
+        }
+    }
+
+    fn accept_before(&self, deadline: Instant) -> Result<UnixStream, XCallbackError> {
+        self.listener.set_nonblocking(true)?;
+        while Instant::now() < deadline {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false)?;
+                    return Ok(stream);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(ACCEPT_POLL_INTERVAL);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(XCallbackError::Timeout)
+    }
+}
+
+impl Drop for CallbackListener {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub struct XdgXCallbackClient {
+    opener: Vec<String>,
+}
+
+impl XdgXCallbackClient {
+    pub fn new() -> XdgXCallbackClient {
+        XdgXCallbackClient {
+            opener: vec![DEFAULT_OPENER.to_string()],
+        }
+    }
+
+    /// Use `opener` instead of `xdg-open` to launch target apps
+    ///
+    /// The first item is the program to run and the rest are passed to it as arguments, followed
+    /// by the URL to open.
+    pub fn with_opener<I, T>(mut self, opener: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: ToString,
+    {
+        let opener: Vec<_> = opener.into_iter().map(|s| s.to_string()).collect();
+        if !opener.is_empty() {
+            self.opener = opener;
+        }
+        self
+    }
+
+    pub fn opener(&self) -> &[String] {
+        &self.opener
+    }
+
+    fn execute_and_wait(
+        &self,
+        url: &XCallbackUrl,
+        timeout: Option<Duration>,
+    ) -> Result<XCallbackResponse, XCallbackError> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let callback_id = generate_callback_id();
+        let listener = CallbackListener::bind(&callback_id)?;
+        let callback_url = generate_callback_url(url, &callback_id);
+        open(&self.opener, &callback_url, deadline)?;
+        let callback_url = listener.receive(deadline)?;
+        callback_url::callback_url_to_response(callback_url)
+    }
+}
+
+impl Default for XdgXCallbackClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl XCallbackClient for XdgXCallbackClient {
+    fn execute(&self, url: &XCallbackUrl) -> Result<XCallbackResponse, XCallbackError> {
+        self.execute_and_wait(url, None)
+    }
+
+    fn execute_with_timeout(
+        &self,
+        url: &XCallbackUrl,
+        timeout: Duration,
+    ) -> Result<XCallbackResponse, XCallbackError> {
+        self.execute_and_wait(url, Some(timeout))
+    }
+
+    fn request_url(&self, url: &XCallbackUrl) -> XCallbackUrl {
+        generate_callback_url(url, &generate_callback_id())
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncXCallbackClient for XdgXCallbackClient {
+    type Future = XdgExecuteFuture;
+
+    fn execute(&self, url: &XCallbackUrl) -> XdgExecuteFuture {
+        let callback_id = generate_callback_id();
+        let callback_url = generate_callback_url(url, &callback_id);
+        let (sender, receiver) = oneshot::channel();
+
+        match CallbackListener::bind(&callback_id) {
+            Ok(listener) => {
+                let opener = self.opener.clone();
+                thread::spawn(move || {
+                    let result =
+                        open(&opener, &callback_url, None).and_then(|_| listener.receive(None));
+                    let _ = sender.send(result);
+                });
+            }
+            Err(e) => {
+                let _ = sender.send(Err(e.into()));
+            }
+        }
+
+        XdgExecuteFuture {
+            socket_path: socket_path(&callback_id),
+            receiver,
+            done: false,
+        }
+    }
+}
+
+/// Response of a single `AsyncXCallbackClient::execute` call
+#[cfg(feature = "async")]
+pub struct XdgExecuteFuture {
+    socket_path: PathBuf,
+    receiver: oneshot::Receiver<Result<XCallbackUrl, XCallbackError>>,
+    done: bool,
+}
+
+#[cfg(feature = "async")]
+impl Future for XdgExecuteFuture {
+    type Output = Result<XCallbackResponse, XCallbackError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let result = match Pin::new(&mut self.receiver).poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        self.done = true;
+        Poll::Ready(
+            result
+                .map_err(|_| XCallbackError::Disconnected)
+                .and_then(|result| result)
+                .and_then(callback_url::callback_url_to_response),
+        )
+    }
+}
+
+#[cfg(feature = "async")]
+impl Drop for XdgExecuteFuture {
+    fn drop(&mut self) {
+        if !self.done {
+            // Wake the listener thread so it can clean up its socket
+            let _ = UnixStream::connect(&self.socket_path);
+        }
+    }
+}
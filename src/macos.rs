@@ -1,31 +1,28 @@
-use crate::client::{XCallbackClient, XCallbackResponse, XCallbackStatus};
+use crate::callback_url::{self, generate_callback_id, generate_callback_url, Route};
+#[cfg(feature = "async")]
+use crate::client::AsyncXCallbackClient;
+use crate::client::{XCallbackClient, XCallbackResponse};
+use crate::pending::{Callback, PendingRequests};
 use crate::x_callback_url::*;
 use ::macos::appkit::*;
 use ::macos::foundation::*;
 use ::macos::{impl_objc_class, Id, ObjCClass};
+#[cfg(feature = "async")]
+use futures_channel::oneshot;
 use objc::declare::ClassDecl;
 use objc::runtime::*;
-use rand::distributions::Alphanumeric;
-use rand::{thread_rng, Rng};
-use std::collections::HashMap;
-use std::error::Error;
-use std::sync::mpsc::{Receiver, Sender};
-use std::sync::Mutex;
-use std::sync::{mpsc, Once};
-
-const CALLBACK_SCHEME: &str = "callback";
-const CALLBACK_SOURCE: &str = "callback";
-const CALLBACK_ACTION_SUCCESS: &str = "success";
-const CALLBACK_ACTION_ERROR: &str = "error";
-const CALLBACK_ACTION_CANCEL: &str = "cancel";
-const CALLBACK_PARAM_KEY_CALLBACK_ID: &str = "callback_id";
+#[cfg(feature = "async")]
+use std::future::Future;
+#[cfg(feature = "async")]
+use std::pin::Pin;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Once;
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
+use std::time::Duration;
 
 lazy_static! {
-    static ref CALLBACK_URL_BASE: XCallbackUrl = XCallbackUrl::new(CALLBACK_SCHEME);
-}
-
-lazy_static! {
-    static ref SENDERS: Mutex<HashMap<String, Sender<XCallbackUrl>>> = Mutex::new(HashMap::new());
+    static ref PENDING: PendingRequests = PendingRequests::new();
 }
 
 pub fn run_app() {
@@ -40,117 +37,114 @@ pub fn terminate_app() {
     app.terminate(&app);
 }
 
-pub struct NSXCallbackClient {
-    callback_id: String,
-    receiver: Receiver<XCallbackUrl>,
-}
+pub struct NSXCallbackClient;
 
 impl NSXCallbackClient {
     pub fn new() -> NSXCallbackClient {
-        let callback_id = NSXCallbackClient::generate_callback_id();
-        let (sender, receiver) = mpsc::channel();
-        NSXCallbackClient::store_sender(&callback_id, sender);
-        NSXCallbackClient {
-            callback_id,
-            receiver,
-        }
+        NSXCallbackClient
     }
 
-    fn generate_callback_id() -> String {
-        thread_rng().sample_iter(&Alphanumeric).take(32).collect()
+    fn execute_and_wait(
+        &self,
+        url: &XCallbackUrl,
+        timeout: Option<Duration>,
+    ) -> Result<XCallbackResponse, XCallbackError> {
+        let callback_id = generate_callback_id();
+        let receiver = PENDING.register(&callback_id);
+        let result = open(&generate_callback_url(url, &callback_id))
+            .and_then(|_| NSXCallbackClient::wait_for_response(&receiver, timeout));
+        PENDING.remove(&callback_id);
+        result
     }
 
-    fn store_sender(callback_id: &str, sender: Sender<XCallbackUrl>) {
-        SENDERS
-            .lock()
-            .unwrap()
-            .insert(callback_id.to_string(), sender);
+    fn wait_for_response(
+        receiver: &Receiver<Callback>,
+        timeout: Option<Duration>,
+    ) -> Result<XCallbackResponse, XCallbackError> {
+        let callback_url = match timeout {
+            Some(timeout) => receiver.recv_timeout(timeout).map_err(|e| match e {
+                RecvTimeoutError::Timeout => XCallbackError::Timeout,
+                RecvTimeoutError::Disconnected => XCallbackError::Disconnected,
+            })?,
+            None => receiver.recv().map_err(|_| XCallbackError::Disconnected)?,
+        };
+        callback_url::callback_url_to_response(callback_url?)
     }
+}
 
-    fn generate_callback_url(&self, url: &XCallbackUrl) -> XCallbackUrl {
-        fn generate_callback_url(action: &str, callback_id: &str) -> String {
-            let mut url = CALLBACK_URL_BASE.clone();
-            url.set_action(action);
-            url.action_params_mut()
-                .push(CALLBACK_PARAM_KEY_CALLBACK_ID, callback_id);
-            url.to_string()
-        }
+impl Default for NSXCallbackClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        let mut callback_url = url.clone();
-        callback_url
-            .callback_params_mut()
-            .set_source(Some(CALLBACK_SOURCE));
-        callback_url
-            .callback_params_mut()
-            .set_success(Some(generate_callback_url(
-                CALLBACK_ACTION_SUCCESS,
-                &self.callback_id,
-            )));
-        callback_url
-            .callback_params_mut()
-            .set_error(Some(generate_callback_url(
-                CALLBACK_ACTION_ERROR,
-                &self.callback_id,
-            )));
-        callback_url
-            .callback_params_mut()
-            .set_cancel(Some(generate_callback_url(
-                CALLBACK_ACTION_CANCEL,
-                &self.callback_id,
-            )));
-        callback_url
+impl XCallbackClient for NSXCallbackClient {
+    fn execute(&self, url: &XCallbackUrl) -> Result<XCallbackResponse, XCallbackError> {
+        self.execute_and_wait(url, None)
     }
 
-    fn wait_for_response(&self) -> Result<XCallbackResponse, Box<dyn Error>> {
-        let callback_url = self.receiver.recv()?;
-        NSXCallbackClient::callback_url_to_response(callback_url)
+    fn execute_with_timeout(
+        &self,
+        url: &XCallbackUrl,
+        timeout: Duration,
+    ) -> Result<XCallbackResponse, XCallbackError> {
+        self.execute_and_wait(url, Some(timeout))
     }
 
-    fn callback_url_to_response(
-        callback_url: XCallbackUrl,
-    ) -> Result<XCallbackResponse, Box<dyn Error>> {
-        let status = match callback_url.action() {
-            CALLBACK_ACTION_SUCCESS => XCallbackStatus::Success,
-            CALLBACK_ACTION_ERROR => XCallbackStatus::Error,
-            CALLBACK_ACTION_CANCEL => XCallbackStatus::Cancel,
-            action => return Err(Box::new(XCallbackError::InvalidAction(action.to_string()))),
-        };
-        let action_params = callback_url
-            .action_params()
-            .iter()
-            .filter(|(k, _)| k != CALLBACK_PARAM_KEY_CALLBACK_ID)
-            .map(|(k, v)| (k.to_string(), v.to_string()))
-            .collect();
-
-        Ok(XCallbackResponse {
-            status,
-            action_params,
-        })
+    fn request_url(&self, url: &XCallbackUrl) -> XCallbackUrl {
+        generate_callback_url(url, &generate_callback_id())
     }
 }
 
-impl Default for NSXCallbackClient {
-    fn default() -> Self {
-        Self::new()
+#[cfg(feature = "async")]
+impl AsyncXCallbackClient for NSXCallbackClient {
+    type Future = NSExecuteFuture;
+
+    fn execute(&self, url: &XCallbackUrl) -> NSExecuteFuture {
+        let callback_id = generate_callback_id();
+        let receiver = PENDING.register_oneshot(&callback_id);
+        if let Err(e) = open(&generate_callback_url(url, &callback_id)) {
+            PENDING.deliver(&callback_id, Err(e));
+        }
+        NSExecuteFuture {
+            callback_id,
+            receiver,
+        }
     }
 }
 
-impl Drop for NSXCallbackClient {
-    fn drop(&mut self) {
-        SENDERS.lock().unwrap().remove(&self.callback_id);
+/// Response of a single `AsyncXCallbackClient::execute` call
+#[cfg(feature = "async")]
+pub struct NSExecuteFuture {
+    callback_id: String,
+    receiver: oneshot::Receiver<Callback>,
+}
+
+#[cfg(feature = "async")]
+impl Future for NSExecuteFuture {
+    type Output = Result<XCallbackResponse, XCallbackError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.receiver).poll(cx).map(|result| {
+            result
+                .map_err(|_| XCallbackError::Disconnected)
+                .and_then(|callback| callback)
+                .and_then(callback_url::callback_url_to_response)
+        })
     }
 }
 
-impl XCallbackClient for NSXCallbackClient {
-    fn execute(&self, url: &XCallbackUrl) -> Result<XCallbackResponse, Box<dyn Error>> {
-        let callback_url = self.generate_callback_url(url);
-        open(&callback_url);
-        self.wait_for_response()
+#[cfg(feature = "async")]
+impl Drop for NSExecuteFuture {
+    fn drop(&mut self) {
+        PENDING.remove(&self.callback_id);
     }
 }
 
-pub fn open(url: &XCallbackUrl) {
-    NSWorkspace::shared_workspace().open_url(NSURL::from(NSString::from(&url.to_string())))
+pub fn open(url: &XCallbackUrl) -> Result<(), XCallbackError> {
+    let url = url.to_url()?;
+    NSWorkspace::shared_workspace().open_url(NSURL::from(NSString::from(&url.to_string())));
+    Ok(())
 }
 
 impl_objc_class!(AppDelegate);
@@ -181,22 +175,20 @@ impl Default for AppDelegate {
                 _reply_event: Id,
             ) {
                 let url = NSAppleEventDescriptor::from_ptr(event)
-                    .and_then(|event| event.url_param_value())
-                    .and_then(|url| url.as_str())
-                    .and_then(|s| XCallbackUrl::parse(s).ok())
-                    .unwrap();
-
-                let callback_id = url
-                    .action_params()
-                    .iter()
-                    .find(|(k, _)| k == CALLBACK_PARAM_KEY_CALLBACK_ID)
-                    .unwrap()
-                    .1
-                    .to_string();
-                let senders = SENDERS.lock().unwrap();
-                let sender = senders.get(&callback_id).unwrap();
-
-                sender.send(url).unwrap();
+                    .and_then(|event| event.url_param_value());
+                let input = url.as_ref().and_then(|url| url.as_str());
+
+                match callback_url::route_callback(input) {
+                    Route::Deliver {
+                        callback_id,
+                        callback,
+                    } => {
+                        if !PENDING.deliver(&callback_id, callback) {
+                            eprintln!("Ignoring callback for unknown request: {}", callback_id);
+                        }
+                    }
+                    Route::Ignore(e) => eprintln!("Ignoring callback: {}", e),
+                }
             }
 
             unsafe {
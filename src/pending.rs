@@ -0,0 +1,192 @@
+use crate::x_callback_url::{XCallbackError, XCallbackUrl};
+#[cfg(feature = "async")]
+use futures_channel::oneshot;
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+
+/// Callback URL a target app called back with, or why it could not be understood
+pub type Callback = Result<XCallbackUrl, XCallbackError>;
+
+enum CallbackSender {
+    Channel(Sender<Callback>),
+    #[cfg(feature = "async")]
+    Oneshot(oneshot::Sender<Callback>),
+}
+
+impl CallbackSender {
+    fn send(self, callback: Callback) -> bool {
+        match self {
+            CallbackSender::Channel(sender) => sender.send(callback).is_ok(),
+            #[cfg(feature = "async")]
+            CallbackSender::Oneshot(sender) => sender.send(callback).is_ok(),
+        }
+    }
+}
+
+/// Requests waiting for their target app to call back, keyed by callback id
+#[derive(Default)]
+pub struct PendingRequests {
+    senders: Mutex<HashMap<String, CallbackSender>>,
+}
+
+impl PendingRequests {
+    pub fn new() -> PendingRequests {
+        Default::default()
+    }
+
+    pub fn register(&self, callback_id: &str) -> Receiver<Callback> {
+        let (sender, receiver) = mpsc::channel();
+        self.insert(callback_id, CallbackSender::Channel(sender));
+        receiver
+    }
+
+    #[cfg(feature = "async")]
+    pub fn register_oneshot(&self, callback_id: &str) -> oneshot::Receiver<Callback> {
+        let (sender, receiver) = oneshot::channel();
+        self.insert(callback_id, CallbackSender::Oneshot(sender));
+        receiver
+    }
+
+    fn insert(&self, callback_id: &str, sender: CallbackSender) {
+        self.senders
+            .lock()
+            .unwrap()
+            .insert(callback_id.to_string(), sender);
+    }
+
+    pub fn remove(&self, callback_id: &str) {
+        self.senders.lock().unwrap().remove(callback_id);
+    }
+
+    /// Hand `callback` to the request waiting on `callback_id`
+    ///
+    /// Each request receives at most one callback. Returns `false` if no request is waiting on
+    /// `callback_id` anymore.
+    pub fn deliver(&self, callback_id: &str, callback: Callback) -> bool {
+        let sender = self.senders.lock().unwrap().remove(callback_id);
+        match sender {
+            Some(sender) => sender.send(callback),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pending::{Callback, PendingRequests};
+    use crate::x_callback_url::{XCallbackError, XCallbackUrl};
+
+    fn callback_url(action: &str) -> Callback {
+        let mut url = XCallbackUrl::new("callback");
+        url.set_action(action);
+        Ok(url)
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let pending = PendingRequests::new();
+        let first = pending.register("first");
+        let second = pending.register("second");
+
+        assert!(pending.deliver("second", callback_url("error")));
+        assert!(pending.deliver("first", callback_url("success")));
+
+        assert_eq!(first.recv().unwrap().unwrap().action(), "success");
+        assert_eq!(second.recv().unwrap().unwrap().action(), "error");
+    }
+
+    #[test]
+    fn test_interleaved() {
+        let pending = PendingRequests::new();
+        let first = pending.register("first");
+        let second = pending.register("second");
+
+        assert!(pending.deliver("second", callback_url("cancel")));
+        assert_eq!(second.recv().unwrap().unwrap().action(), "cancel");
+        assert!(first.try_recv().is_err());
+
+        let third = pending.register("third");
+        assert!(pending.deliver("third", callback_url("error")));
+        assert!(pending.deliver("first", callback_url("success")));
+
+        assert_eq!(first.recv().unwrap().unwrap().action(), "success");
+        assert_eq!(third.recv().unwrap().unwrap().action(), "error");
+    }
+
+    #[test]
+    fn test_unknown_callback_id() {
+        let pending = PendingRequests::new();
+        let _receiver = pending.register("known");
+
+        assert!(!pending.deliver("unknown", callback_url("success")));
+    }
+
+    #[test]
+    fn test_delivered_once() {
+        let pending = PendingRequests::new();
+        let receiver = pending.register("once");
+
+        assert!(pending.deliver("once", callback_url("success")));
+
+        assert!(!pending.deliver("once", callback_url("success")));
+        assert_eq!(receiver.recv().unwrap().unwrap().action(), "success");
+    }
+
+    #[test]
+    fn test_removed() {
+        let pending = PendingRequests::new();
+        let _receiver = pending.register("removed");
+
+        pending.remove("removed");
+
+        assert!(!pending.deliver("removed", callback_url("success")));
+    }
+
+    #[test]
+    fn test_receiver_dropped() {
+        let pending = PendingRequests::new();
+        drop(pending.register("dropped"));
+
+        assert!(!pending.deliver("dropped", callback_url("success")));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let pending = PendingRequests::new();
+        let receiver = pending.register("malformed");
+
+        assert!(pending.deliver(
+            "malformed",
+            Err(XCallbackError::MalformedCallback(
+                "Invalid action".to_string()
+            ))
+        ));
+
+        assert!(matches!(
+            receiver.recv().unwrap(),
+            Err(XCallbackError::MalformedCallback(_))
+        ));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_oneshot_out_of_order() {
+        let pending = PendingRequests::new();
+        let mut first = pending.register_oneshot("first");
+        let mut second = pending.register_oneshot("second");
+
+        assert!(pending.deliver("second", callback_url("error")));
+        assert!(first.try_recv().unwrap().is_none());
+        assert!(pending.deliver("first", callback_url("success")));
+
+        assert_eq!(
+            first.try_recv().unwrap().unwrap().unwrap().action(),
+            "success"
+        );
+        assert_eq!(
+            second.try_recv().unwrap().unwrap().unwrap().action(),
+            "error"
+        );
+    }
+}
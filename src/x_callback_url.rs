@@ -1,9 +1,22 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::borrow::{Borrow, Cow};
+use std::convert::TryFrom;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
+use std::io;
 use std::iter::FromIterator;
+use std::str::FromStr;
 use url::Url;
 
+mod param_value;
+
+pub use self::param_value::{
+    BoolStyle, FromCallbackParams, IntoCallbackParams, ParamValue, LIST_SEPARATOR,
+};
+#[cfg(feature = "derive")]
+pub use callback_derive::{FromCallbackParams, IntoCallbackParams};
+
 const CALLBACK_HOST: &str = "x-callback-url";
 pub const CALLBACK_PARAM_KEY_SOURCE: &str = "x-source";
 pub const CALLBACK_PARAM_KEY_SUCCESS: &str = "x-success";
@@ -11,11 +24,19 @@ pub const CALLBACK_PARAM_KEY_ERROR: &str = "x-error";
 pub const CALLBACK_PARAM_KEY_CANCEL: &str = "x-cancel";
 
 #[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct CallbackParams {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     source: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     success: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     error: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     cancel: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    extra_callback_params: Vec<(String, String)>,
 }
 
 impl CallbackParams {
@@ -51,7 +72,40 @@ impl CallbackParams {
         self.cancel = cancel.map(|s| s.to_string());
     }
 
-    pub fn iter(&self) -> CallbackParamsIter {
+    /// `x-` params other than `x-source`, `x-success`, `x-error` and `x-cancel`, in the order
+    /// they were added
+    ///
+    /// Apps use these for their own extensions, such as `x-source-id`.
+    pub fn extra_callback_params(&self) -> &[(String, String)] {
+        &self.extra_callback_params
+    }
+
+    pub fn extra_callback_param(&self, key: &str) -> Option<&str> {
+        self.extra_callback_params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Set, replace or with `None` remove the extra callback param `key`
+    ///
+    /// `key` should start with `x-`, otherwise it is parsed back as an action param.
+    pub fn set_extra_callback_param<K: ToString, V: ToString>(&mut self, key: K, value: Option<V>) {
+        let key = key.to_string();
+        match value {
+            Some(value) => match self
+                .extra_callback_params
+                .iter_mut()
+                .find(|(k, _)| *k == key)
+            {
+                Some((_, v)) => *v = value.to_string(),
+                None => self.extra_callback_params.push((key, value.to_string())),
+            },
+            None => self.extra_callback_params.retain(|(k, _)| *k != key),
+        }
+    }
+
+    pub fn iter(&self) -> CallbackParamsIter<'_> {
         let callback_params: Vec<_> = vec![
             (CALLBACK_PARAM_KEY_SOURCE, &self.source),
             (CALLBACK_PARAM_KEY_SUCCESS, &self.success),
@@ -60,9 +114,16 @@ impl CallbackParams {
         ]
         .into_iter()
         .filter_map(|(k, v)| v.as_ref().map(|v| (k, v.as_ref())))
+        .chain(
+            self.extra_callback_params
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str())),
+        )
         .collect();
 
-        CallbackParamsIter { callback_params }
+        CallbackParamsIter {
+            callback_params: callback_params.into_iter(),
+        }
     }
 }
 
@@ -80,6 +141,9 @@ where
                 CALLBACK_PARAM_KEY_SUCCESS => callback_params.set_success(Some(v.to_string())),
                 CALLBACK_PARAM_KEY_ERROR => callback_params.set_error(Some(v.to_string())),
                 CALLBACK_PARAM_KEY_CANCEL => callback_params.set_cancel(Some(v.to_string())),
+                _ if ActionParams::is_callback_param(&key) => callback_params
+                    .extra_callback_params
+                    .push((key, v.to_string())),
                 _ => {}
             }
         }
@@ -89,7 +153,7 @@ where
 }
 
 pub struct CallbackParamsIter<'a> {
-    callback_params: Vec<(&'a str, &'a str)>,
+    callback_params: std::vec::IntoIter<(&'a str, &'a str)>,
 }
 
 impl<'a> Iterator for CallbackParamsIter<'a> {
@@ -97,12 +161,14 @@ impl<'a> Iterator for CallbackParamsIter<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         self.callback_params
-            .pop()
+            .next()
             .map(|(k, v)| (Cow::Borrowed(k), Cow::Borrowed(v)))
     }
 }
 
 #[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct ActionParams {
     action_params: Vec<(String, String)>,
 }
@@ -142,11 +208,174 @@ impl ActionParams {
         key.as_ref().starts_with("x-")
     }
 
-    pub fn iter(&self) -> ActionParamsIter {
+    pub fn iter(&self) -> ActionParamsIter<'_> {
         ActionParamsIter {
             action_params: &self.action_params,
         }
     }
+
+    pub fn len(&self) -> usize {
+        self.action_params.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.action_params.is_empty()
+    }
+
+    /// Value of the first param with `key`
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.action_params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Values of every param with `key`, in order
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.action_params
+            .iter()
+            .filter(move |(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.action_params.iter().any(|(k, _)| k == key)
+    }
+
+    /// Remove every param with `key`, returning the value of the first one
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let mut removed = None;
+        self.action_params.retain(|(k, v)| {
+            if k != key {
+                return true;
+            }
+            if removed.is_none() {
+                removed = Some(v.clone());
+            }
+            false
+        });
+        removed
+    }
+
+    /// Replace every param with `key` by a single one with `value`
+    ///
+    /// The param keeps the position of the first one it replaces, or is added at the end.
+    pub fn set<K, V>(&mut self, key: K, value: V)
+    where
+        K: ToString,
+        V: ToString,
+    {
+        let key = key.to_string();
+        match self.action_params.iter().position(|(k, _)| *k == key) {
+            Some(index) => {
+                self.action_params[index].1 = value.to_string();
+                let mut index = index + 1;
+                while index < self.action_params.len() {
+                    if self.action_params[index].0 == key {
+                        self.action_params.remove(index);
+                    } else {
+                        index += 1;
+                    }
+                }
+            }
+            None => self.action_params.push((key, value.to_string())),
+        }
+    }
+
+    /// Value of the first param with `key` read as a `T`
+    pub fn get_as<T: ParamValue>(&self, key: &str) -> Result<Option<T>, XCallbackError> {
+        self.get(key)
+            .map(|value| param_value(key, value))
+            .transpose()
+    }
+
+    /// Value of the first param with `key` parsed with `T::from_str`
+    ///
+    /// For types from other crates, such as dates, that can't implement `ParamValue`.
+    pub fn get_parsed<T: FromStr>(&self, key: &str) -> Result<Option<T>, XCallbackError> {
+        self.get(key)
+            .map(|value| parsed_value(key, value))
+            .transpose()
+    }
+
+    /// Items of the first list param with `key`, or none if there is no such param
+    pub fn get_list<T: ParamValue>(&self, key: &str) -> Result<Vec<T>, XCallbackError> {
+        self.get_as(key).map(Option::unwrap_or_default)
+    }
+
+    pub fn push_as<K: ToString, T: ParamValue>(&mut self, key: K, value: &T) {
+        self.push(key, value.to_param());
+    }
+
+    pub fn push_bool<K: ToString>(&mut self, key: K, value: bool, style: BoolStyle) {
+        self.push(key, style.format(value));
+    }
+
+    /// Add a param with `items` joined by `LIST_SEPARATOR`
+    pub fn push_list<K, I>(&mut self, key: K, items: I)
+    where
+        K: ToString,
+        I: IntoIterator,
+        I::Item: ToString,
+    {
+        let items: Vec<_> = items.into_iter().map(|item| item.to_string()).collect();
+        self.push(key, items.join(&LIST_SEPARATOR.to_string()));
+    }
+
+    /// Keep only the params for which `f(key, value)` returns `true`
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&str, &str) -> bool,
+    {
+        self.action_params.retain(|(k, v)| f(k, v));
+    }
+}
+
+pub(crate) fn param_value<T: ParamValue>(key: &str, value: &str) -> Result<T, XCallbackError> {
+    T::from_param(value).ok_or_else(|| XCallbackError::InvalidParamValue {
+        key: key.to_string(),
+        value: value.to_string(),
+    })
+}
+
+pub(crate) fn parsed_value<T: FromStr>(key: &str, value: &str) -> Result<T, XCallbackError> {
+    value
+        .parse()
+        .map_err(|_| XCallbackError::InvalidParamValue {
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+}
+
+impl<K, V> Extend<(K, V)> for ActionParams
+where
+    K: ToString,
+    V: ToString,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        self.action_params.extend(
+            iter.into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string())),
+        );
+    }
+}
+
+impl IntoIterator for ActionParams {
+    type Item = (String, String);
+    type IntoIter = std::vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.action_params.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ActionParams {
+    type Item = (Cow<'a, str>, Cow<'a, str>);
+    type IntoIter = ActionParamsIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
 }
 
 impl<T> FromIterator<(T, T)> for ActionParams
@@ -189,31 +418,57 @@ impl<'a> Iterator for ActionParamsIter<'a> {
     }
 }
 
-// impl<'a> IntoIterator for AcionParams {
-//     type Item = (Cow<'a, str>, Cow<'a, str>);
-//     type IntoIter = std::vec::IntoIter<Self::Item>;
-//
-//     fn into_iter(self) -> Self::IntoIter {
-//         self.
-//     }
-// }
-
-#[derive(Debug, Clone, PartialEq)]
+/// An x-callback-url
+///
+/// With the `serde` feature this serializes as its scheme, action and params. Use
+/// `url_string` to serialize it as a URL string instead. Both forms accept the same URLs as
+/// `XCallbackUrl::parse`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "XCallbackUrlParts"))]
 pub struct XCallbackUrl {
     scheme: String,
     action: String,
+    #[cfg_attr(feature = "serde", serde(default))]
     action_params: ActionParams,
+    #[cfg_attr(feature = "serde", serde(default))]
     callback_params: CallbackParams,
+    /// Query pairs in the order they were parsed
+    #[cfg_attr(feature = "serde", serde(skip))]
+    param_order: Vec<(String, String)>,
+}
+
+/// URLs are equal if their parts are, regardless of the order their params were parsed in
+impl PartialEq for XCallbackUrl {
+    fn eq(&self, other: &Self) -> bool {
+        self.scheme == other.scheme
+            && self.action == other.action
+            && self.action_params == other.action_params
+            && self.callback_params == other.callback_params
+    }
+}
+
+/// Order of the query params when serializing an `XCallbackUrl`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamOrder {
+    /// The order the params were parsed in, followed by any params added or changed since in
+    /// canonical order
+    Preserve,
+    /// Action params followed by `x-source`, `x-success`, `x-error`, `x-cancel` and extra
+    /// callback params
+    Canonical,
 }
 
 impl XCallbackUrl {
-    pub fn parse(input: &str) -> Result<XCallbackUrl, Box<dyn Error>> {
-        let url = Url::parse(input)?;
+    pub fn parse(input: &str) -> Result<XCallbackUrl, XCallbackError> {
+        XCallbackUrl::try_from(Url::parse(input)?)
+    }
 
+    fn from_url(url: &Url) -> Result<XCallbackUrl, XCallbackError> {
         if !url.host_str().eq(&Some(CALLBACK_HOST)) {
-            return Err(Box::new(XCallbackError::InvalidHost(
+            return Err(XCallbackError::InvalidHost(
                 url.host_str().unwrap_or("").to_string(),
-            )));
+            ));
         }
 
         let scheme = url.scheme().to_string();
@@ -229,12 +484,41 @@ impl XCallbackUrl {
             action,
             action_params: ActionParams::from_iter(url.query_pairs()),
             callback_params: CallbackParams::from_iter(url.query_pairs()),
+            param_order: url.query_pairs().into_owned().collect(),
         })
     }
 
+    /// Start building a URL for the app registered for `scheme`
+    pub fn builder<T: ToString>(scheme: T) -> XCallbackUrlBuilder {
+        XCallbackUrlBuilder {
+            url: XCallbackUrl::with_scheme(scheme.to_string()),
+        }
+    }
+
+    /// Like `parse`, but with every check in `ParseOptions::strict` enabled
+    pub fn parse_strict(input: &str) -> Result<XCallbackUrl, XCallbackError> {
+        ParseOptions::strict().parse(input)
+    }
+
+    /// `scheme` should be a valid URL scheme, which debug builds assert. Use `try_new` or
+    /// `builder` to check it instead.
     pub fn new(scheme: &str) -> Self {
+        debug_assert!(is_valid_scheme(scheme), "invalid scheme: {:?}", scheme);
+        XCallbackUrl::with_scheme(scheme.to_string())
+    }
+
+    /// Like `new`, but returns `XCallbackError::InvalidScheme` if `scheme` is not a valid URL
+    /// scheme
+    pub fn try_new(scheme: &str) -> Result<Self, XCallbackError> {
+        if !is_valid_scheme(scheme) {
+            return Err(XCallbackError::InvalidScheme(scheme.to_string()));
+        }
+        Ok(XCallbackUrl::with_scheme(scheme.to_string()))
+    }
+
+    fn with_scheme(scheme: String) -> Self {
         XCallbackUrl {
-            scheme: scheme.to_string(),
+            scheme,
             action: "".to_string(),
             action_params: ActionParams {
                 action_params: vec![],
@@ -244,7 +528,9 @@ impl XCallbackUrl {
                 success: None,
                 error: None,
                 cancel: None,
+                extra_callback_params: vec![],
             },
+            param_order: vec![],
         }
     }
 
@@ -252,8 +538,14 @@ impl XCallbackUrl {
         &self.scheme
     }
 
+    /// `scheme` should be a valid URL scheme, see `new`
     pub fn set_scheme<T: ToString>(&mut self, scheme: T) {
         self.scheme = scheme.to_string();
+        debug_assert!(
+            is_valid_scheme(&self.scheme),
+            "invalid scheme: {:?}",
+            self.scheme
+        );
     }
 
     pub fn action(&self) -> &str {
@@ -280,7 +572,31 @@ impl XCallbackUrl {
         &mut self.callback_params
     }
 
-    pub fn to_url(&self) -> Result<Url, url::ParseError> {
+    /// Add an action param, or set the callback param if `key` starts with `x-`
+    ///
+    /// Callback params replace any already set with the same key, like parsing does.
+    pub fn add_param<K: ToString, V: ToString>(&mut self, key: K, value: V) {
+        let key = key.to_string();
+        let callback_params = &mut self.callback_params;
+        match key.as_str() {
+            CALLBACK_PARAM_KEY_SOURCE => callback_params.set_source(Some(value)),
+            CALLBACK_PARAM_KEY_SUCCESS => callback_params.set_success(Some(value)),
+            CALLBACK_PARAM_KEY_ERROR => callback_params.set_error(Some(value)),
+            CALLBACK_PARAM_KEY_CANCEL => callback_params.set_cancel(Some(value)),
+            _ if ActionParams::is_callback_param(&key) => {
+                callback_params.set_extra_callback_param(key, Some(value))
+            }
+            _ => self.action_params.push(key, value),
+        }
+    }
+
+    /// Serialize with `ParamOrder::Preserve`, so that parsing and serializing a URL gives back
+    /// the same URL
+    pub fn to_url(&self) -> Result<Url, XCallbackError> {
+        self.to_url_with_order(ParamOrder::Preserve)
+    }
+
+    pub fn to_url_with_order(&self, order: ParamOrder) -> Result<Url, XCallbackError> {
         let mut url = Url::parse(&format!(
             "{scheme}://{host}/{action}",
             host = CALLBACK_HOST,
@@ -288,24 +604,393 @@ impl XCallbackUrl {
             action = self.action,
         ))?;
 
+        let query_pairs = self.ordered_params(order);
+        if !query_pairs.is_empty() {
+            url.query_pairs_mut().extend_pairs(&query_pairs);
+        }
+
+        Ok(url)
+    }
+
+    fn ordered_params(&self, order: ParamOrder) -> Vec<(Cow<'_, str>, Cow<'_, str>)> {
         let query_pairs: Vec<_> = self
             .action_params
             .iter()
             .chain(self.callback_params.iter())
             .collect();
+        match order {
+            ParamOrder::Preserve => preserve_order(&self.param_order, query_pairs),
+            ParamOrder::Canonical => query_pairs,
+        }
+    }
+}
 
-        if !query_pairs.is_empty() {
-            url.query_pairs_mut().extend_pairs(&query_pairs);
+/// Fluent way to put together an `XCallbackUrl`
+///
+/// ```
+/// use callback::x_callback_url::XCallbackUrl;
+///
+/// let url = XCallbackUrl::builder("bear")
+///     .action("create")
+///     .param("title", "My Note")
+///     .source("callback")
+///     .on_success("callback://x-callback-url/success")
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(
+///     url.to_string(),
+///     "bear://x-callback-url/create\
+///         ?title=My+Note\
+///         &x-source=callback\
+///         &x-success=callback%3A%2F%2Fx-callback-url%2Fsuccess"
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct XCallbackUrlBuilder {
+    url: XCallbackUrl,
+}
+
+impl XCallbackUrlBuilder {
+    pub fn action<T: ToString>(mut self, action: T) -> Self {
+        self.url.set_action(action);
+        self
+    }
+
+    /// Add an action param, keeping any already added with the same key
+    ///
+    /// `x-` params are callback params instead, see `XCallbackUrl::add_param`.
+    pub fn param<K: ToString, V: ToString>(mut self, key: K, value: V) -> Self {
+        self.url.add_param(key, value);
+        self
+    }
+
+    pub fn params<I, K, V>(mut self, params: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(K, V)>,
+        K: ToString,
+        V: ToString,
+    {
+        for param in params {
+            let (key, value) = param.borrow();
+            self.url.add_param(key.to_string(), value.to_string());
         }
+        self
+    }
 
-        Ok(url)
+    pub fn source<T: ToString>(mut self, source: T) -> Self {
+        self.url.callback_params_mut().set_source(Some(source));
+        self
+    }
+
+    pub fn on_success<T: ToString>(mut self, url: T) -> Self {
+        self.url.callback_params_mut().set_success(Some(url));
+        self
+    }
+
+    pub fn on_error<T: ToString>(mut self, url: T) -> Self {
+        self.url.callback_params_mut().set_error(Some(url));
+        self
+    }
+
+    pub fn on_cancel<T: ToString>(mut self, url: T) -> Self {
+        self.url.callback_params_mut().set_cancel(Some(url));
+        self
+    }
+
+    /// Set an app-specific `x-` param, see `CallbackParams::extra_callback_params`
+    pub fn callback_param<K: ToString, V: ToString>(mut self, key: K, value: V) -> Self {
+        self.url
+            .callback_params_mut()
+            .set_extra_callback_param(key, Some(value));
+        self
+    }
+
+    /// Check that the URL has a valid scheme, an action without `/`, `?` or `#`, valid callback
+    /// URLs and no `x-` action params
+    pub fn build(self) -> Result<XCallbackUrl, XCallbackError> {
+        if self.url.action.is_empty() {
+            return Err(XCallbackError::MissingAction);
+        }
+        // They would end the action, so the URL would be read back differently
+        if self.url.action.contains(['/', '?', '#']) {
+            return Err(XCallbackError::InvalidAction(self.url.action));
+        }
+        // They would be read back as callback params
+        if let Some((key, _)) = self
+            .url
+            .action_params
+            .iter()
+            .find(|(key, _)| ActionParams::is_callback_param(key))
+        {
+            return Err(XCallbackError::ReservedActionParam(key.into_owned()));
+        }
+        if !is_valid_scheme(&self.url.scheme) {
+            return Err(XCallbackError::InvalidScheme(self.url.scheme));
+        }
+        self.url.to_url()?;
+
+        let callback_params = &self.url.callback_params;
+        for (key, value) in &[
+            (CALLBACK_PARAM_KEY_SUCCESS, &callback_params.success),
+            (CALLBACK_PARAM_KEY_ERROR, &callback_params.error),
+            (CALLBACK_PARAM_KEY_CANCEL, &callback_params.cancel),
+        ] {
+            if let Some(value) = value {
+                if let Err(e) = Url::parse(value) {
+                    return Err(XCallbackError::InvalidCallbackUrl(key.to_string(), e));
+                }
+            }
+        }
+
+        Ok(self.url)
     }
 }
 
+/// The structured form of an `XCallbackUrl`, before it is checked
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct XCallbackUrlParts {
+    scheme: String,
+    action: String,
+    #[serde(default)]
+    action_params: ActionParams,
+    #[serde(default)]
+    callback_params: CallbackParams,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<XCallbackUrlParts> for XCallbackUrl {
+    type Error = XCallbackError;
+
+    /// The scheme is the only part `parse` checks that the parts can get wrong
+    fn try_from(parts: XCallbackUrlParts) -> Result<Self, Self::Error> {
+        if !is_valid_scheme(&parts.scheme) {
+            return Err(XCallbackError::InvalidScheme(parts.scheme));
+        }
+        Ok(XCallbackUrl {
+            scheme: parts.scheme,
+            action: parts.action,
+            action_params: parts.action_params,
+            callback_params: parts.callback_params,
+            param_order: vec![],
+        })
+    }
+}
+
+/// Serialize an `XCallbackUrl` as a URL string rather than as its parts
+///
+/// ```
+/// # use callback::x_callback_url::XCallbackUrl;
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Serialize, Deserialize)]
+/// struct Shortcut {
+///     name: String,
+///     #[serde(with = "callback::x_callback_url::url_string")]
+///     url: XCallbackUrl,
+/// }
+/// ```
+#[cfg(feature = "serde")]
+pub mod url_string {
+    use crate::x_callback_url::XCallbackUrl;
+    use serde::{de, ser, Deserialize, Deserializer, Serializer};
+    use std::borrow::Cow;
+
+    pub fn serialize<S: Serializer>(url: &XCallbackUrl, serializer: S) -> Result<S::Ok, S::Error> {
+        let url = url.to_url().map_err(ser::Error::custom)?;
+        serializer.serialize_str(url.as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<XCallbackUrl, D::Error> {
+        let input = Cow::<str>::deserialize(deserializer)?;
+        XCallbackUrl::parse(&input).map_err(de::Error::custom)
+    }
+}
+
+/// Move `query_pairs` that are in `order` to the front, in that order
+///
+/// Pairs are matched on both key and value, so a param that was changed or removed and added
+/// again goes after the parsed ones.
+fn preserve_order<'a>(
+    order: &[(String, String)],
+    query_pairs: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+) -> Vec<(Cow<'a, str>, Cow<'a, str>)> {
+    let mut remaining: Vec<_> = query_pairs.into_iter().map(Some).collect();
+    let mut ordered = Vec::with_capacity(remaining.len());
+    for (key, value) in order {
+        let pair = remaining
+            .iter_mut()
+            .find(|pair| matches!(pair, Some((k, v)) if k == key && v == value))
+            .and_then(Option::take);
+        ordered.extend(pair);
+    }
+    ordered.extend(remaining.into_iter().flatten());
+    ordered
+}
+
+/// `ALPHA *( ALPHA / DIGIT / "+" / "-" / "." )` as in RFC 3986
+fn is_valid_scheme(scheme: &str) -> bool {
+    let mut chars = scheme.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+}
+
+/// Extra checks to run when parsing an `XCallbackUrl`
+///
+/// Every check is off by default, which accepts the same URLs as `XCallbackUrl::parse`. Failed
+/// checks, and hosts other than `x-callback-url`, are reported as `XCallbackError::InvalidAt` with
+/// the byte offset of the offending part of the input.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    check_scheme: bool,
+    require_action: bool,
+    reject_duplicate_callback_params: bool,
+    reject_unknown_callback_params: bool,
+    check_callback_urls: bool,
+}
+
+impl ParseOptions {
+    pub fn new() -> ParseOptions {
+        Default::default()
+    }
+
+    /// Every check enabled
+    pub fn strict() -> ParseOptions {
+        ParseOptions::new()
+            .check_scheme(true)
+            .require_action(true)
+            .reject_duplicate_callback_params(true)
+            .reject_unknown_callback_params(true)
+            .check_callback_urls(true)
+    }
+
+    /// Reject schemes that are not valid according to RFC 3986 before parsing anything else
+    pub fn check_scheme(mut self, check_scheme: bool) -> Self {
+        self.check_scheme = check_scheme;
+        self
+    }
+
+    /// Reject URLs with an empty action
+    pub fn require_action(mut self, require_action: bool) -> Self {
+        self.require_action = require_action;
+        self
+    }
+
+    /// Reject `x-source`, `x-success`, `x-error` or `x-cancel` appearing more than once
+    pub fn reject_duplicate_callback_params(mut self, reject: bool) -> Self {
+        self.reject_duplicate_callback_params = reject;
+        self
+    }
+
+    /// Reject `x-` params other than `x-source`, `x-success`, `x-error` and `x-cancel`
+    pub fn reject_unknown_callback_params(mut self, reject: bool) -> Self {
+        self.reject_unknown_callback_params = reject;
+        self
+    }
+
+    /// Reject `x-success`, `x-error` and `x-cancel` values that are not valid URLs themselves
+    pub fn check_callback_urls(mut self, check_callback_urls: bool) -> Self {
+        self.check_callback_urls = check_callback_urls;
+        self
+    }
+
+    pub fn parse(&self, input: &str) -> Result<XCallbackUrl, XCallbackError> {
+        let scheme_end = input.find(':').unwrap_or(input.len());
+        if self.check_scheme && !is_valid_scheme(&input[..scheme_end]) {
+            return Err(XCallbackError::invalid_at(
+                0,
+                XCallbackError::InvalidScheme(input[..scheme_end].to_string()),
+            ));
+        }
+
+        let url = Url::parse(input)?;
+        let authority_start = input.find("://").map_or(scheme_end, |i| i + 3);
+        if !url.host_str().eq(&Some(CALLBACK_HOST)) {
+            return Err(XCallbackError::invalid_at(
+                authority_start,
+                XCallbackError::InvalidHost(url.host_str().unwrap_or("").to_string()),
+            ));
+        }
+
+        let path_start = input[authority_start..]
+            .find(['/', '?', '#'])
+            .map_or(input.len(), |i| authority_start + i);
+        if self.require_action && url.path().len() <= 1 {
+            return Err(XCallbackError::invalid_at(
+                path_start,
+                XCallbackError::MissingAction,
+            ));
+        }
+
+        let mut seen = vec![];
+        for (position, key, value) in query_pairs(input) {
+            if !ActionParams::is_callback_param(&key) {
+                continue;
+            }
+            let known = [
+                CALLBACK_PARAM_KEY_SOURCE,
+                CALLBACK_PARAM_KEY_SUCCESS,
+                CALLBACK_PARAM_KEY_ERROR,
+                CALLBACK_PARAM_KEY_CANCEL,
+            ]
+            .contains(&key.as_str());
+
+            if self.reject_unknown_callback_params && !known {
+                return Err(XCallbackError::invalid_at(
+                    position,
+                    XCallbackError::UnknownCallbackParam(key),
+                ));
+            }
+            if self.reject_duplicate_callback_params && known && seen.contains(&key) {
+                return Err(XCallbackError::invalid_at(
+                    position,
+                    XCallbackError::DuplicateCallbackParam(key),
+                ));
+            }
+            if self.check_callback_urls && known && key != CALLBACK_PARAM_KEY_SOURCE {
+                if let Err(e) = Url::parse(&value) {
+                    return Err(XCallbackError::invalid_at(
+                        position,
+                        XCallbackError::InvalidCallbackUrl(key, e),
+                    ));
+                }
+            }
+            seen.push(key);
+        }
+
+        XCallbackUrl::parse(input)
+    }
+}
+
+/// Decoded query pairs of `input` along with the byte offset each pair starts at
+fn query_pairs(input: &str) -> Vec<(usize, String, String)> {
+    // A `?` in the fragment doesn't start a query
+    let query_end = input.find('#').unwrap_or(input.len());
+    let query_start = match input[..query_end].find('?') {
+        Some(i) => i + 1,
+        None => return vec![],
+    };
+
+    let mut position = query_start;
+    let mut pairs = vec![];
+    for pair in input[query_start..query_end].split('&') {
+        if let Some((key, value)) = url::form_urlencoded::parse(pair.as_bytes()).next() {
+            pairs.push((position, key.into_owned(), value.into_owned()));
+        }
+        position += pair.len() + 1;
+    }
+    pairs
+}
+
 #[cfg(test)]
 mod test {
     mod x_callback_url {
-        use crate::x_callback_url::XCallbackUrl;
+        use crate::x_callback_url::{ParamOrder, XCallbackError, XCallbackUrl};
+        use std::convert::TryFrom;
+        use url::Url;
 
         #[test]
         fn test() {
@@ -387,36 +1072,818 @@ mod test {
             assert_eq!(url.to_string(), input);
         }
 
+        #[test]
+        fn test_empty_action() {
+            let url = XCallbackUrl::parse("callback://x-callback-url/?key=value").unwrap();
+
+            assert_eq!(url.action(), "");
+            assert_eq!(url.action_params().get("key"), Some("value"));
+        }
+
+        #[test]
+        fn test_errors() {
+            assert!(matches!(
+                XCallbackUrl::parse("not a url"),
+                Err(XCallbackError::Url(_))
+            ));
+            assert!(matches!(
+                XCallbackUrl::parse("callback://elsewhere/action"),
+                Err(XCallbackError::InvalidHost(host)) if host == "elsewhere"
+            ));
+        }
+
+        #[test]
+        fn test_try_new() {
+            assert_eq!(
+                XCallbackUrl::try_new("bear").unwrap(),
+                XCallbackUrl::new("bear")
+            );
+            assert!(matches!(
+                XCallbackUrl::try_new("my app"),
+                Err(XCallbackError::InvalidScheme(scheme)) if scheme == "my app"
+            ));
+        }
+
+        #[test]
+        fn test_invalid_scheme() {
+            let mut url = XCallbackUrl::with_scheme("my app".to_string());
+            url.set_action("create");
+            url.action_params_mut().push("title", "My Note");
+
+            assert!(matches!(url.to_url(), Err(XCallbackError::Url(_))));
+            assert!(matches!(Url::try_from(&url), Err(XCallbackError::Url(_))));
+            assert_eq!(
+                url.to_string(),
+                "my app://x-callback-url/create?title=My+Note"
+            );
+        }
+
+        #[test]
+        fn test_extra_callback_params() {
+            let input = "callback://x-callback-url/action\
+                ?key=value\
+                &x-source-id=42\
+                &x-success=callback%3A%2F%2Fx-callback-success\
+                &x-callback-id=abc";
+
+            let url = XCallbackUrl::parse(input).unwrap();
+
+            assert_eq!(url.action_params().iter().count(), 1);
+            assert_eq!(
+                url.callback_params().extra_callback_params(),
+                &[
+                    ("x-source-id".to_string(), "42".to_string()),
+                    ("x-callback-id".to_string(), "abc".to_string())
+                ]
+            );
+            assert_eq!(
+                url.callback_params().extra_callback_param("x-callback-id"),
+                Some("abc")
+            );
+            assert_eq!(url.callback_params().iter().count(), 3);
+            assert_eq!(XCallbackUrl::parse(&url.to_string()).unwrap(), url);
+        }
+
+        #[test]
+        fn test_set_extra_callback_param() {
+            let mut url = XCallbackUrl::new("callback");
+            url.set_action("action");
+
+            let callback_params = url.callback_params_mut();
+            callback_params.set_extra_callback_param("x-source-id", Some("1"));
+            callback_params.set_extra_callback_param("x-callback-id", Some("abc"));
+            callback_params.set_extra_callback_param("x-source-id", Some("2"));
+            callback_params.set_extra_callback_param("x-callback-id", None::<&str>);
+
+            assert_eq!(
+                url.callback_params().extra_callback_params(),
+                &[("x-source-id".to_string(), "2".to_string())]
+            );
+            assert_eq!(
+                url.to_string(),
+                "callback://x-callback-url/action?x-source-id=2"
+            );
+        }
+
+        #[test]
+        fn test_param_order() {
+            let input = "callback://x-callback-url/action\
+                ?x-success=callback%3A%2F%2Fsuccess\
+                &b=2\
+                &x-source=app\
+                &a=1";
+
+            let mut url = XCallbackUrl::parse(input).unwrap();
+
+            assert_eq!(url.to_string(), input);
+            assert_eq!(
+                url.to_url_with_order(ParamOrder::Canonical)
+                    .unwrap()
+                    .as_str(),
+                "callback://x-callback-url/action\
+                    ?b=2\
+                    &a=1\
+                    &x-source=app\
+                    &x-success=callback%3A%2F%2Fsuccess"
+            );
+
+            url.action_params_mut().push("c", "3");
+            url.callback_params_mut().set_source(None::<&str>);
+            assert_eq!(
+                url.to_string(),
+                "callback://x-callback-url/action\
+                    ?x-success=callback%3A%2F%2Fsuccess\
+                    &b=2\
+                    &a=1\
+                    &c=3"
+            );
+        }
+
+        #[test]
+        fn test_param_order_readded() {
+            let mut url = XCallbackUrl::parse("bear://x-callback-url/create?t=1&u=2&t=3").unwrap();
+
+            url.action_params_mut().remove("t");
+            url.action_params_mut().push("t", "9");
+            assert_eq!(url.to_string(), "bear://x-callback-url/create?u=2&t=9");
+        }
+
+        #[test]
+        fn test_eq_ignores_param_order() {
+            let parsed = XCallbackUrl::parse("bear://x-callback-url/create?title=Note").unwrap();
+            let built = XCallbackUrl::builder("bear")
+                .action("create")
+                .param("title", "Note")
+                .build()
+                .unwrap();
+
+            assert_eq!(parsed, built);
+            assert_eq!(parsed.to_string(), built.to_string());
+        }
+
+        #[test]
+        fn test_conversions() {
+            let input = "bear://x-callback-url/create?title=Note";
+
+            let url: XCallbackUrl = input.parse().unwrap();
+            assert_eq!(XCallbackUrl::try_from(input).unwrap(), url);
+            assert_eq!(
+                XCallbackUrl::try_from(Url::parse(input).unwrap()).unwrap(),
+                url
+            );
+            assert_eq!(format!("{}", url), input);
+            assert_eq!(Url::try_from(&url).unwrap().as_str(), input);
+            assert_eq!(Url::try_from(url).unwrap().as_str(), input);
+
+            assert!(matches!(
+                "bear://elsewhere/create".parse::<XCallbackUrl>(),
+                Err(XCallbackError::InvalidHost(_))
+            ));
+        }
+
         // test action, scheme, and params
     }
+
+    mod round_trip {
+        use crate::x_callback_url::{ParamOrder, XCallbackUrl};
+        use proptest::prelude::*;
+        use proptest::sample::Index;
+        use url::form_urlencoded;
+
+        const CALLBACK_PARAM_KEYS: [&str; 4] = ["x-source", "x-success", "x-error", "x-cancel"];
+
+        /// Well-formed x-callback-urls: form-urlencoded queries without repeated callback params
+        fn x_callback_url() -> impl Strategy<Value = String> {
+            let action_param = ("[a-wyz][a-z0-9_ ]{0,5}", "\\PC{0,8}");
+            let extra_callback_param = ("x-[a-z]{1,5}-[a-z]{1,3}", "\\PC{0,8}");
+            let callback_params = proptest::collection::vec(
+                proptest::option::of((any::<Index>(), "\\PC{0,12}")),
+                CALLBACK_PARAM_KEYS.len(),
+            );
+            (
+                "[a-z][a-z0-9+.-]{0,6}",
+                "[A-Za-z0-9_-]{1,10}",
+                proptest::collection::vec(
+                    prop_oneof![3 => action_param, 1 => extra_callback_param],
+                    0..6,
+                ),
+                callback_params,
+            )
+                .prop_map(|(scheme, action, mut params, callback_params)| {
+                    for (key, param) in CALLBACK_PARAM_KEYS.iter().zip(callback_params) {
+                        if let Some((index, value)) = param {
+                            params.insert(index.index(params.len() + 1), (key.to_string(), value));
+                        }
+                    }
+                    let mut input = format!("{}://x-callback-url/{}", scheme, action);
+                    if !params.is_empty() {
+                        input.push('?');
+                        input.push_str(
+                            &form_urlencoded::Serializer::new(String::new())
+                                .extend_pairs(params)
+                                .finish(),
+                        );
+                    }
+                    input
+                })
+        }
+
+        proptest! {
+            #[test]
+            fn test_preserve(input in x_callback_url()) {
+                prop_assert_eq!(XCallbackUrl::parse(&input).unwrap().to_string(), input);
+            }
+
+            #[test]
+            fn test_canonical(input in x_callback_url()) {
+                let url = XCallbackUrl::parse(&input).unwrap();
+                let canonical = url.to_url_with_order(ParamOrder::Canonical).unwrap();
+                let reparsed = XCallbackUrl::parse(canonical.as_str()).unwrap();
+
+                prop_assert_eq!(reparsed.action_params(), url.action_params());
+                prop_assert_eq!(reparsed.callback_params(), url.callback_params());
+                prop_assert_eq!(
+                    reparsed.to_url_with_order(ParamOrder::Canonical).unwrap(),
+                    canonical.clone()
+                );
+                prop_assert_eq!(reparsed.to_url().unwrap(), canonical);
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    mod serde {
+        use crate::client::{XCallbackResponse, XCallbackStatus};
+        use crate::x_callback_url::{url_string, XCallbackUrl};
+        use serde::{Deserialize, Serialize};
+        use serde_json::json;
+
+        fn url() -> XCallbackUrl {
+            let mut url = XCallbackUrl::new("bear");
+            url.set_action("create");
+            url.action_params_mut().push("title", "Note");
+            url.callback_params_mut()
+                .set_success(Some("callback://x-callback-url/success"));
+            url.callback_params_mut()
+                .set_extra_callback_param("x-source-id", Some("1"));
+            url
+        }
+
+        #[test]
+        fn test_structured() {
+            let value = serde_json::to_value(url()).unwrap();
+
+            assert_eq!(
+                value,
+                json!({
+                    "scheme": "bear",
+                    "action": "create",
+                    "action_params": [["title", "Note"]],
+                    "callback_params": {
+                        "success": "callback://x-callback-url/success",
+                        "extra_callback_params": [["x-source-id", "1"]],
+                    },
+                })
+            );
+            assert_eq!(
+                serde_json::from_value::<XCallbackUrl>(value).unwrap(),
+                url()
+            );
+            assert_eq!(
+                serde_json::from_value::<XCallbackUrl>(json!({"scheme": "bear", "action": "open"}))
+                    .unwrap()
+                    .to_string(),
+                "bear://x-callback-url/open"
+            );
+        }
+
+        #[test]
+        fn test_structured_errors() {
+            assert!(matches!(
+                serde_json::from_value::<XCallbackUrl>(json!({"scheme": "my app", "action": "open"})),
+                Err(e) if e.to_string().contains("my app")
+            ));
+        }
+
+        #[test]
+        fn test_round_trip() {
+            #[derive(Debug, PartialEq, Serialize, Deserialize)]
+            struct Shortcut {
+                #[serde(with = "url_string")]
+                url: XCallbackUrl,
+            }
+
+            // Whatever `parse` accepts comes back the same from either form
+            for input in &[
+                "bear://x-callback-url/",
+                "bear://x-callback-url/create?x-source=App&title=Note",
+                "bear://x-callback-url/create?x-success=nowhere&x-other=1",
+                "bear://x-callback-url/a/b?title=&title=2",
+            ] {
+                let url = XCallbackUrl::parse(input).unwrap();
+
+                let value = serde_json::to_value(&url).unwrap();
+                assert_eq!(serde_json::from_value::<XCallbackUrl>(value).unwrap(), url);
+
+                let shortcut = Shortcut { url: url.clone() };
+                let value = serde_json::to_value(&shortcut).unwrap();
+                assert_eq!(serde_json::from_value::<Shortcut>(value).unwrap(), shortcut);
+            }
+        }
+
+        #[test]
+        fn test_url_string() {
+            #[derive(Debug, PartialEq, Serialize, Deserialize)]
+            struct Shortcut {
+                #[serde(with = "url_string")]
+                url: XCallbackUrl,
+            }
+
+            let shortcut = Shortcut { url: url() };
+            let value = serde_json::to_value(&shortcut).unwrap();
+
+            assert_eq!(
+                value,
+                json!({
+                    "url": "bear://x-callback-url/create\
+                        ?title=Note\
+                        &x-success=callback%3A%2F%2Fx-callback-url%2Fsuccess\
+                        &x-source-id=1"
+                })
+            );
+            let deserialized: Shortcut = serde_json::from_value(value).unwrap();
+            assert_eq!(deserialized, shortcut);
+            assert!(
+                serde_json::from_value::<Shortcut>(json!({"url": "bear://elsewhere/create"}))
+                    .is_err()
+            );
+        }
+
+        #[test]
+        fn test_response() {
+            let response = XCallbackResponse {
+                status: XCallbackStatus::Cancel,
+                action_params: vec![("reason".to_string(), "user".to_string())],
+            };
+
+            let value = serde_json::to_value(&response).unwrap();
+
+            assert_eq!(
+                value,
+                json!({"status": "cancel", "action_params": [["reason", "user"]]})
+            );
+            assert_eq!(
+                serde_json::from_value::<XCallbackResponse>(value).unwrap(),
+                response
+            );
+        }
+    }
+
+    mod action_params {
+        use crate::x_callback_url::ActionParams;
+
+        fn action_params() -> ActionParams {
+            vec![
+                ("tag", "a"),
+                ("title", "Note"),
+                ("tag", "b"),
+                ("x-source", "me"),
+            ]
+            .into_iter()
+            .collect()
+        }
+
+        fn pairs(action_params: ActionParams) -> Vec<(String, String)> {
+            action_params.into_iter().collect()
+        }
+
+        #[test]
+        fn test_lookup() {
+            let action_params = action_params();
+
+            assert_eq!(action_params.len(), 3);
+            assert!(!action_params.is_empty());
+            assert!(ActionParams::default().is_empty());
+            assert_eq!(action_params.get("tag"), Some("a"));
+            assert_eq!(action_params.get("x-source"), None);
+            assert_eq!(action_params.get_all("tag").collect::<Vec<_>>(), ["a", "b"]);
+            assert!(action_params.contains_key("title"));
+            assert!(!action_params.contains_key("text"));
+        }
+
+        #[test]
+        fn test_remove() {
+            let mut action_params = action_params();
+
+            assert_eq!(action_params.remove("tag"), Some("a".to_string()));
+            assert_eq!(action_params.remove("tag"), None);
+            assert_eq!(pairs(action_params), [("title".into(), "Note".into())]);
+        }
+
+        #[test]
+        fn test_set() {
+            let mut action_params = action_params();
+
+            action_params.set("tag", "c");
+            action_params.set("text", "Body");
+
+            assert_eq!(
+                pairs(action_params),
+                [
+                    ("tag".into(), "c".into()),
+                    ("title".into(), "Note".into()),
+                    ("text".into(), "Body".into())
+                ]
+            );
+        }
+
+        #[test]
+        fn test_retain_and_extend() {
+            let mut action_params = action_params();
+
+            action_params.retain(|k, v| k != "tag" || v == "b");
+            action_params.extend(vec![("text", "Body")]);
+
+            let borrowed: Vec<_> = (&action_params)
+                .into_iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect();
+            assert_eq!(borrowed, ["title=Note", "tag=b", "text=Body"]);
+        }
+    }
+
+    mod builder {
+        use crate::x_callback_url::{XCallbackError, XCallbackUrl};
+
+        #[test]
+        fn test_build() {
+            let url = XCallbackUrl::builder("bear")
+                .action("create")
+                .param("tag", "a")
+                .params(&[("tag", "b"), ("title", "Note")])
+                .source("me")
+                .on_success("callback://x-callback-url/success")
+                .on_error("callback://x-callback-url/error")
+                .on_cancel("callback://x-callback-url/cancel")
+                .callback_param("x-source-id", 1)
+                .build()
+                .unwrap();
+
+            let mut expected = XCallbackUrl::new("bear");
+            expected.set_action("create");
+            expected
+                .action_params_mut()
+                .append(&[("tag", "a"), ("tag", "b"), ("title", "Note")]);
+            let callback_params = expected.callback_params_mut();
+            callback_params.set_source(Some("me"));
+            callback_params.set_success(Some("callback://x-callback-url/success"));
+            callback_params.set_error(Some("callback://x-callback-url/error"));
+            callback_params.set_cancel(Some("callback://x-callback-url/cancel"));
+            callback_params.set_extra_callback_param("x-source-id", Some(1));
+            assert_eq!(url, expected);
+        }
+
+        #[test]
+        fn test_build_errors() {
+            assert!(matches!(
+                XCallbackUrl::builder("bear").build(),
+                Err(XCallbackError::MissingAction)
+            ));
+            assert!(matches!(
+                XCallbackUrl::builder("my app").action("create").build(),
+                Err(XCallbackError::InvalidScheme(_))
+            ));
+            assert!(matches!(
+                XCallbackUrl::builder("bear")
+                    .action("create")
+                    .on_cancel("nowhere")
+                    .build(),
+                Err(XCallbackError::InvalidCallbackUrl(key, _)) if key == "x-cancel"
+            ));
+
+            let mut builder = XCallbackUrl::builder("bear").action("create");
+            builder.url.action_params_mut().push("x-source", "me");
+            assert!(matches!(
+                builder.build(),
+                Err(XCallbackError::ReservedActionParam(key)) if key == "x-source"
+            ));
+
+            for action in &["create?x-success=evil#x", "notes/create", "create#x"] {
+                assert!(matches!(
+                    XCallbackUrl::builder("bear").action(action).build(),
+                    Err(XCallbackError::InvalidAction(a)) if a == *action
+                ));
+            }
+        }
+
+        #[test]
+        fn test_build_callback_params() {
+            let url = XCallbackUrl::builder("bear")
+                .action("create")
+                .source("App")
+                .params(&[("title", "Note"), ("x-source", "me"), ("x-app", "1")])
+                .param("x-app", "2")
+                .build()
+                .unwrap();
+
+            assert_eq!(url.action_params().len(), 1);
+            assert_eq!(url.callback_params().source(), Some("me"));
+            assert_eq!(
+                url.to_string(),
+                "bear://x-callback-url/create?title=Note&x-source=me&x-app=2"
+            );
+        }
+    }
+
+    mod parse_options {
+        use crate::x_callback_url::{ParseOptions, XCallbackError, XCallbackUrl};
+
+        fn rejected_at(input: &str) -> (usize, XCallbackError) {
+            match XCallbackUrl::parse_strict(input) {
+                Err(XCallbackError::InvalidAt { position, error }) => (position, *error),
+                result => panic!("expected {} to be rejected, got {:?}", input, result),
+            }
+        }
+
+        #[test]
+        fn test_strict() {
+            let input = "bear://x-callback-url/create\
+                ?title=Note\
+                &x-source=Callback\
+                &x-success=callback%3A%2F%2Fx-callback-url%2Fsuccess";
+
+            assert_eq!(
+                XCallbackUrl::parse_strict(input).unwrap(),
+                XCallbackUrl::parse(input).unwrap()
+            );
+        }
+
+        #[test]
+        fn test_invalid_scheme() {
+            let (position, error) = rejected_at("my_app://x-callback-url/create");
+            assert_eq!(position, 0);
+            assert!(matches!(error, XCallbackError::InvalidScheme(scheme) if scheme == "my_app"));
+        }
+
+        #[test]
+        fn test_missing_action() {
+            let (position, error) = rejected_at("bear://x-callback-url/?title=Note");
+            assert_eq!(position, 21);
+            assert!(matches!(error, XCallbackError::MissingAction));
+
+            let (position, _) = rejected_at("bear://x-callback-url");
+            assert_eq!(position, 21);
+        }
+
+        #[test]
+        fn test_duplicate_callback_param() {
+            let (position, error) =
+                rejected_at("bear://x-callback-url/create?x-success=a:b&x-success=c:d");
+            assert_eq!(position, 43);
+            assert!(
+                matches!(error, XCallbackError::DuplicateCallbackParam(key) if key == "x-success")
+            );
+        }
+
+        #[test]
+        fn test_unknown_callback_param() {
+            let (position, error) = rejected_at("bear://x-callback-url/create?a=b&x-source-id=1");
+            assert_eq!(position, 33);
+            assert!(
+                matches!(error, XCallbackError::UnknownCallbackParam(key) if key == "x-source-id")
+            );
+        }
+
+        #[test]
+        fn test_invalid_callback_url() {
+            let (position, error) = rejected_at("bear://x-callback-url/create?x-error=nowhere");
+            assert_eq!(position, 29);
+            assert!(
+                matches!(error, XCallbackError::InvalidCallbackUrl(key, _) if key == "x-error")
+            );
+        }
+
+        #[test]
+        fn test_invalid_host() {
+            let (position, error) = rejected_at("bear://elsewhere/create");
+            assert_eq!(position, 7);
+            assert!(matches!(error, XCallbackError::InvalidHost(host) if host == "elsewhere"));
+        }
+
+        #[test]
+        fn test_fragment() {
+            let input = "bear://x-callback-url/create#a?x-success=1&x-success=2";
+            assert_eq!(
+                XCallbackUrl::parse_strict(input).unwrap(),
+                XCallbackUrl::parse(input).unwrap()
+            );
+
+            let (position, _) = rejected_at("bear://x-callback-url/create?x-other=1#a?b");
+            assert_eq!(position, 29);
+        }
+
+        #[test]
+        fn test_checks_disabled() {
+            let input = "bear://x-callback-url/create?x-success=nowhere&x-success=a:b&x-other=1";
+
+            let url = ParseOptions::new().parse(input).unwrap();
+
+            assert_eq!(url.callback_params().success(), Some("a:b"));
+            assert_eq!(
+                ParseOptions::new().parse("bear://x-callback-url/").unwrap(),
+                XCallbackUrl::parse("bear://x-callback-url/").unwrap()
+            );
+        }
+    }
 }
 
-impl ToString for XCallbackUrl {
-    fn to_string(&self) -> String {
-        self.to_url()
-            .ok()
-            .map(|u| u.to_string())
-            .unwrap_or_else(|| "".to_string())
+/// Formats the URL as `to_url` would
+///
+/// A URL with an invalid scheme, which `to_url` rejects, is formatted with its scheme and action as
+/// they are.
+impl Display for XCallbackUrl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Ok(url) = self.to_url() {
+            return f.write_str(url.as_str());
+        }
+
+        write!(f, "{}://{}/{}", self.scheme, CALLBACK_HOST, self.action)?;
+        let query_pairs = self.ordered_params(ParamOrder::Preserve);
+        if !query_pairs.is_empty() {
+            let query = url::form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(&query_pairs)
+                .finish();
+            write!(f, "?{}", query)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for XCallbackUrl {
+    type Err = XCallbackError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        XCallbackUrl::parse(s)
     }
 }
 
+impl TryFrom<&str> for XCallbackUrl {
+    type Error = XCallbackError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        XCallbackUrl::parse(value)
+    }
+}
+
+impl TryFrom<Url> for XCallbackUrl {
+    type Error = XCallbackError;
+
+    fn try_from(value: Url) -> Result<Self, Self::Error> {
+        XCallbackUrl::from_url(&value)
+    }
+}
+
+impl TryFrom<&Url> for XCallbackUrl {
+    type Error = XCallbackError;
+
+    fn try_from(value: &Url) -> Result<Self, Self::Error> {
+        XCallbackUrl::from_url(value)
+    }
+}
+
+impl TryFrom<&XCallbackUrl> for Url {
+    type Error = XCallbackError;
+
+    fn try_from(value: &XCallbackUrl) -> Result<Self, Self::Error> {
+        value.to_url()
+    }
+}
+
+impl TryFrom<XCallbackUrl> for Url {
+    type Error = XCallbackError;
+
+    fn try_from(value: XCallbackUrl) -> Result<Self, Self::Error> {
+        value.to_url()
+    }
+}
+
+/// Everything that can go wrong parsing, building or executing an x-callback-url
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum XCallbackError {
+    /// The URL is not syntactically valid
+    Url(url::ParseError),
+    /// The scheme is not a valid URL scheme
+    InvalidScheme(String),
+    /// The host is not `x-callback-url`
     InvalidHost(String),
+    /// The URL has no action
+    MissingAction,
+    /// The callback action is not `success`, `error` or `cancel`, or a built action contains `/`,
+    /// `?` or `#`
     InvalidAction(String),
+    /// The target app could not be opened or the callback could not be passed on to the client
+    Transport(io::Error),
+    /// The target app did not call back in time
+    Timeout,
+    /// The client stopped waiting for the callback before it arrived
+    Disconnected,
+    /// The target app called back with a URL that could not be understood
+    MalformedCallback(String),
+    /// A callback param appears more than once
+    DuplicateCallbackParam(String),
+    /// An action param has an `x-` key, which is reserved for callback params
+    ReservedActionParam(String),
+    /// The value of a param is not valid for the type it was read as
+    InvalidParamValue { key: String, value: String },
+    /// A param required by `FromCallbackParams` is missing
+    MissingParam(String),
+    /// An `x-` param is not one of the callback params defined by x-callback-url
+    UnknownCallbackParam(String),
+    /// The value of a callback param is not a valid URL
+    InvalidCallbackUrl(String, url::ParseError),
+    /// `error` was found at byte offset `position` of the input
+    InvalidAt {
+        position: usize,
+        error: Box<XCallbackError>,
+    },
+}
+
+impl XCallbackError {
+    fn invalid_at(position: usize, error: XCallbackError) -> XCallbackError {
+        XCallbackError::InvalidAt {
+            position,
+            error: Box::new(error),
+        }
+    }
 }
 
 impl Display for XCallbackError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
+            XCallbackError::Url(e) => f.write_fmt(format_args!("Invalid URL: {}", e)),
+            XCallbackError::InvalidScheme(scheme) => {
+                f.write_fmt(format_args!("Invalid scheme: {}", scheme))
+            }
             XCallbackError::InvalidHost(host) => {
                 f.write_fmt(format_args!("Invalid host: {}", host))
             }
+            XCallbackError::MissingAction => f.write_str("Missing action"),
             XCallbackError::InvalidAction(action) => {
                 f.write_fmt(format_args!("Invalid action: {}", action))
             }
+            XCallbackError::Transport(e) => f.write_fmt(format_args!("{}", e)),
+            XCallbackError::Timeout => f.write_str("Timed out waiting for callback"),
+            XCallbackError::Disconnected => f.write_str("Stopped waiting for callback"),
+            XCallbackError::MalformedCallback(reason) => {
+                f.write_fmt(format_args!("Malformed callback: {}", reason))
+            }
+            XCallbackError::DuplicateCallbackParam(key) => {
+                f.write_fmt(format_args!("Duplicate callback param: {}", key))
+            }
+            XCallbackError::ReservedActionParam(key) => f.write_fmt(format_args!(
+                "Action param with a callback param key: {}",
+                key
+            )),
+            XCallbackError::UnknownCallbackParam(key) => {
+                f.write_fmt(format_args!("Unknown callback param: {}", key))
+            }
+            XCallbackError::MissingParam(key) => {
+                f.write_fmt(format_args!("Missing param: {}", key))
+            }
+            XCallbackError::InvalidParamValue { key, value } => {
+                f.write_fmt(format_args!("Invalid value for {}: {}", key, value))
+            }
+            XCallbackError::InvalidCallbackUrl(key, e) => {
+                f.write_fmt(format_args!("Invalid URL in {}: {}", key, e))
+            }
+            XCallbackError::InvalidAt { position, error } => {
+                f.write_fmt(format_args!("{} at position {}", error, position))
+            }
+        }
+    }
+}
+
+impl Error for XCallbackError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            XCallbackError::Url(e) => Some(e),
+            XCallbackError::Transport(e) => Some(e),
+            XCallbackError::InvalidCallbackUrl(_, e) => Some(e),
+            XCallbackError::InvalidAt { error, .. } => Some(error.as_ref()),
+            _ => None,
         }
     }
 }
 
-impl Error for XCallbackError {}
+impl From<url::ParseError> for XCallbackError {
+    fn from(e: url::ParseError) -> Self {
+        XCallbackError::Url(e)
+    }
+}
+
+impl From<io::Error> for XCallbackError {
+    fn from(e: io::Error) -> Self {
+        XCallbackError::Transport(e)
+    }
+}
@@ -0,0 +1,200 @@
+//! Typed action param values
+//!
+//! ```
+//! use callback::x_callback_url::{BoolStyle, XCallbackUrl};
+//!
+//! let mut url = XCallbackUrl::new("bear");
+//! url.set_action("create");
+//! url.action_params_mut().push_bool("pin", true, BoolStyle::YesNo);
+//! url.action_params_mut().push_list("tags", ["work", "todo"]);
+//! url.action_params_mut().push_as("priority", &2);
+//!
+//! let action_params = url.action_params();
+//! assert_eq!(action_params.get("pin"), Some("yes"));
+//! assert_eq!(action_params.get_as::<bool>("pin").unwrap(), Some(true));
+//! assert_eq!(action_params.get_list::<String>("tags").unwrap(), ["work", "todo"]);
+//! assert_eq!(action_params.get_as::<u8>("priority").unwrap(), Some(2));
+//! ```
+
+use crate::x_callback_url::{ActionParams, XCallbackError};
+
+/// Separator between the items of list params
+pub const LIST_SEPARATOR: char = ',';
+
+/// A type that can be written to and read from an action param
+///
+/// Implemented for strings, `bool`, the number types and `Vec`s of those, which are joined with
+/// `,`. Implement it for your own types to read and write them with `ActionParams::get_as` and
+/// `ActionParams::push_as`. Types from other crates can be read with `ActionParams::get_parsed`
+/// instead.
+pub trait ParamValue: Sized {
+    fn to_param(&self) -> String;
+
+    /// `None` if `value` is not a valid `Self`
+    fn from_param(value: &str) -> Option<Self>;
+}
+
+/// How to write `bool` params, since apps disagree on it
+///
+/// Reading a `bool` accepts every style.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BoolStyle {
+    #[default]
+    TrueFalse,
+    YesNo,
+    OneZero,
+}
+
+impl BoolStyle {
+    pub fn format(self, value: bool) -> &'static str {
+        match (self, value) {
+            (BoolStyle::TrueFalse, true) => "true",
+            (BoolStyle::TrueFalse, false) => "false",
+            (BoolStyle::YesNo, true) => "yes",
+            (BoolStyle::YesNo, false) => "no",
+            (BoolStyle::OneZero, true) => "1",
+            (BoolStyle::OneZero, false) => "0",
+        }
+    }
+}
+
+impl ParamValue for bool {
+    fn to_param(&self) -> String {
+        BoolStyle::default().format(*self).to_string()
+    }
+
+    fn from_param(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "1" => Some(true),
+            "false" | "no" | "0" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl ParamValue for String {
+    fn to_param(&self) -> String {
+        self.clone()
+    }
+
+    fn from_param(value: &str) -> Option<Self> {
+        Some(value.to_string())
+    }
+}
+
+macro_rules! impl_param_value_from_str {
+    ($($t:ty),*) => {
+        $(
+            impl ParamValue for $t {
+                fn to_param(&self) -> String {
+                    self.to_string()
+                }
+
+                fn from_param(value: &str) -> Option<Self> {
+                    value.parse().ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_param_value_from_str!(
+    char, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64
+);
+
+/// Items joined with `,`, so items must not contain `,` themselves
+impl<T: ParamValue> ParamValue for Vec<T> {
+    fn to_param(&self) -> String {
+        self.iter()
+            .map(ParamValue::to_param)
+            .collect::<Vec<_>>()
+            .join(&LIST_SEPARATOR.to_string())
+    }
+
+    fn from_param(value: &str) -> Option<Self> {
+        if value.is_empty() {
+            return Some(vec![]);
+        }
+        value.split(LIST_SEPARATOR).map(T::from_param).collect()
+    }
+}
+
+/// A type that can be read from action params, such as those of an `XCallbackResponse`
+///
+/// Derive it with `#[derive(FromCallbackParams)]` and the `derive` feature.
+pub trait FromCallbackParams: Sized {
+    fn from_callback_params(params: &ActionParams) -> Result<Self, XCallbackError>;
+}
+
+/// A type that can be turned into action params, such as those of a request
+///
+/// Derive it with `#[derive(IntoCallbackParams)]` and the `derive` feature.
+pub trait IntoCallbackParams {
+    fn into_callback_params(self) -> ActionParams;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::x_callback_url::{ActionParams, BoolStyle, ParamValue, XCallbackError};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn test_bool() {
+        for (style, expected) in &[
+            (BoolStyle::TrueFalse, "true"),
+            (BoolStyle::YesNo, "yes"),
+            (BoolStyle::OneZero, "1"),
+        ] {
+            let mut action_params = ActionParams::default();
+            action_params.push_bool("flag", true, *style);
+
+            assert_eq!(action_params.get("flag"), Some(*expected));
+            assert_eq!(action_params.get_as::<bool>("flag").unwrap(), Some(true));
+        }
+        assert_eq!(bool::from_param("No"), Some(false));
+        assert_eq!(bool::from_param("maybe"), None);
+    }
+
+    #[test]
+    fn test_list() {
+        let mut action_params = ActionParams::default();
+        action_params.push_list("ids", [1, 2, 3]);
+        action_params.push_list("none", Vec::<u8>::new());
+
+        assert_eq!(action_params.get("ids"), Some("1,2,3"));
+        assert_eq!(action_params.get_list::<u32>("ids").unwrap(), [1, 2, 3]);
+        assert!(action_params.get_list::<u32>("none").unwrap().is_empty());
+        assert!(action_params.get_list::<u32>("missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut action_params = ActionParams::default();
+        action_params.push("count", "many");
+
+        assert_eq!(action_params.get_as::<u32>("missing").unwrap(), None);
+        assert!(matches!(
+            action_params.get_as::<u32>("count"),
+            Err(XCallbackError::InvalidParamValue { key, value })
+                if key == "count" && value == "many"
+        ));
+    }
+
+    #[test]
+    fn test_parsed() {
+        let mut action_params = ActionParams::default();
+        action_params.push("addr", "127.0.0.1");
+        action_params.push("count", "many");
+
+        assert_eq!(
+            action_params.get_parsed::<IpAddr>("addr").unwrap(),
+            Some(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        );
+        assert_eq!(action_params.get_parsed::<IpAddr>("missing").unwrap(), None);
+        assert!(matches!(
+            action_params.get_parsed::<u32>("count"),
+            Err(XCallbackError::InvalidParamValue { key, value })
+                if key == "count" && value == "many"
+        ));
+    }
+}
@@ -0,0 +1,119 @@
+//! End-to-end round trips through the async Linux backend, the `cli` callback handler and
+//! `echo-target`
+#![cfg(all(
+    feature = "linux",
+    feature = "async",
+    feature = "cli",
+    target_os = "linux"
+))]
+
+mod common;
+
+use callback::client::{AsyncXCallbackClient, XCallbackStatus};
+use callback::linux::XdgXCallbackClient;
+use common::url;
+use std::env;
+use std::fs;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, Once};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Sockets go in a directory of their own so the tests can check they are cleaned up
+fn runtime_dir() -> PathBuf {
+    static INIT: Once = Once::new();
+    let dir = env::temp_dir().join(format!("callback-async-test-{}", std::process::id()));
+    INIT.call_once(|| {
+        fs::create_dir_all(&dir).unwrap();
+        env::set_var("XDG_RUNTIME_DIR", &dir);
+    });
+    dir
+}
+
+/// Keeps the tests from seeing each other's sockets
+fn serial() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn client() -> XdgXCallbackClient {
+    runtime_dir();
+    common::client()
+}
+
+struct ThreadWaker(thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+fn sockets() -> Vec<PathBuf> {
+    fs::read_dir(runtime_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sock"))
+        .collect()
+}
+
+#[test]
+fn test_concurrent_futures() {
+    let _serial = serial();
+    let client = client();
+    let start = Instant::now();
+
+    let futures: Vec<_> = (0..3)
+        .map(|n| {
+            AsyncXCallbackClient::execute(
+                &client,
+                &url("delay", &[("seconds", "1"), ("n", &n.to_string())]),
+            )
+        })
+        .collect();
+
+    for (n, future) in futures.into_iter().enumerate() {
+        let response = block_on(future).unwrap();
+        assert_eq!(response.status, XCallbackStatus::Success);
+        assert_eq!(response.get("n"), Some(n.to_string().as_str()));
+    }
+    // One after the other, the requests would take at least the sum of their delays
+    assert!(start.elapsed() < Duration::from_secs(3));
+}
+
+#[test]
+fn test_drop_future() {
+    let _serial = serial();
+    let client = client();
+
+    // An opener that never calls back, so only dropping the future stops its listener
+    let silent = XdgXCallbackClient::new().with_opener(vec!["true"]);
+    let dropped = AsyncXCallbackClient::execute(&silent, &url("echo", &[]));
+    let fail = AsyncXCallbackClient::execute(&client, &url("fail", &[("code", "1")]));
+    let echo = AsyncXCallbackClient::execute(&client, &url("echo", &[("n", "1")]));
+    drop(dropped);
+
+    assert_eq!(block_on(fail).unwrap().status, XCallbackStatus::Error);
+    assert_eq!(block_on(echo).unwrap().get("n"), Some("1"));
+
+    // The dropped request's listener is woken up and removes its socket
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !sockets().is_empty() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(50));
+    }
+    assert_eq!(sockets(), Vec::<PathBuf>::new());
+}
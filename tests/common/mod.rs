@@ -0,0 +1,20 @@
+//! Fixtures shared by the end-to-end tests
+
+use callback::linux::XdgXCallbackClient;
+use callback::x_callback_url::XCallbackUrl;
+
+/// Opens target URLs with `echo-target`, which opens callback URLs with the `cli` handler
+pub fn client() -> XdgXCallbackClient {
+    XdgXCallbackClient::new().with_opener(vec![
+        env!("CARGO_BIN_EXE_echo-target"),
+        "--opener",
+        env!("CARGO_BIN_EXE_cli"),
+    ])
+}
+
+pub fn url(action: &str, params: &[(&str, &str)]) -> XCallbackUrl {
+    let mut url = XCallbackUrl::new("echo-target");
+    url.set_action(action);
+    url.action_params_mut().append(params);
+    url
+}
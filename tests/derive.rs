@@ -0,0 +1,99 @@
+//! `#[derive(FromCallbackParams)]` and `#[derive(IntoCallbackParams)]`
+#![cfg(feature = "derive")]
+
+use callback::client::{XCallbackResponse, XCallbackStatus};
+use callback::x_callback_url::{
+    ActionParams, FromCallbackParams, IntoCallbackParams, XCallbackError, XCallbackUrl,
+};
+
+#[derive(Debug, PartialEq, IntoCallbackParams)]
+struct CreateNote {
+    title: String,
+    #[callback(rename = "open_note")]
+    open: bool,
+    tags: Vec<String>,
+    text: Option<String>,
+}
+
+#[derive(Debug, PartialEq, FromCallbackParams)]
+struct CreatedNote {
+    identifier: String,
+    #[callback(rename = "title")]
+    name: String,
+    #[callback(default)]
+    pinned: bool,
+    tags: Option<Vec<String>>,
+}
+
+fn response(params: &[(&str, &str)]) -> XCallbackResponse {
+    XCallbackResponse {
+        status: XCallbackStatus::Success,
+        action_params: params
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    }
+}
+
+#[test]
+fn test_into_callback_params() {
+    let request = CreateNote {
+        title: "Note".to_string(),
+        open: false,
+        tags: vec!["a".to_string(), "b".to_string()],
+        text: None,
+    };
+
+    let url = XCallbackUrl::builder("bear")
+        .action("create")
+        .params(request.into_callback_params())
+        .build()
+        .unwrap();
+
+    assert_eq!(
+        url.to_string(),
+        "bear://x-callback-url/create?title=Note&open_note=false&tags=a%2Cb"
+    );
+}
+
+#[test]
+fn test_from_callback_params() {
+    let note: CreatedNote = response(&[("identifier", "1"), ("title", "Note")])
+        .params_as()
+        .unwrap();
+
+    assert_eq!(
+        note,
+        CreatedNote {
+            identifier: "1".to_string(),
+            name: "Note".to_string(),
+            pinned: false,
+            tags: None,
+        }
+    );
+
+    let note: CreatedNote = response(&[
+        ("identifier", "2"),
+        ("title", "Other"),
+        ("pinned", "yes"),
+        ("tags", "a,b"),
+    ])
+    .params_as()
+    .unwrap();
+
+    assert!(note.pinned);
+    assert_eq!(note.tags, Some(vec!["a".to_string(), "b".to_string()]));
+}
+
+#[test]
+fn test_from_callback_params_errors() {
+    assert!(matches!(
+        CreatedNote::from_callback_params(&ActionParams::default()),
+        Err(XCallbackError::MissingParam(key)) if key == "identifier"
+    ));
+    assert!(matches!(
+        response(&[("identifier", "1"), ("title", "Note"), ("pinned", "maybe")])
+            .params_as::<CreatedNote>(),
+        Err(XCallbackError::InvalidParamValue { key, .. }) if key == "pinned"
+    ));
+}
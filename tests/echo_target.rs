@@ -0,0 +1,330 @@
+//! End-to-end round trips through the Linux backend, the `cli` callback handler and `echo-target`
+#![cfg(all(feature = "linux", feature = "cli", target_os = "linux"))]
+
+mod common;
+
+use callback::client::{XCallbackClient, XCallbackStatus};
+use callback::linux::XdgXCallbackClient;
+use callback::x_callback_url::{XCallbackError, XCallbackUrl};
+use common::{client, url};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn params(params: &[(&str, &str)]) -> Vec<(String, String)> {
+    params
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_echo() {
+    let response = client()
+        .execute(&url("echo", &[("title", "My Note"), ("text", "a=b&c")]))
+        .unwrap();
+
+    assert_eq!(response.status, XCallbackStatus::Success);
+    assert_eq!(
+        response.action_params,
+        params(&[("title", "My Note"), ("text", "a=b&c")])
+    );
+}
+
+#[test]
+fn test_fail() {
+    let response = client()
+        .execute(&url("fail", &[("code", "42"), ("message", "Not found")]))
+        .unwrap();
+
+    assert_eq!(response.status, XCallbackStatus::Error);
+    assert_eq!(
+        response.action_params,
+        params(&[("errorCode", "42"), ("errorMessage", "Not found")])
+    );
+}
+
+#[test]
+fn test_cancel() {
+    let response = client().execute(&url("cancel", &[])).unwrap();
+
+    assert_eq!(response.status, XCallbackStatus::Cancel);
+    assert!(response.action_params.is_empty());
+}
+
+#[test]
+fn test_delay() {
+    let response = client()
+        .execute_with_timeout(
+            &url("delay", &[("seconds", "0.1")]),
+            Duration::from_secs(10),
+        )
+        .unwrap();
+
+    assert_eq!(response.status, XCallbackStatus::Success);
+    assert_eq!(response.action_params, params(&[("seconds", "0.1")]));
+}
+
+#[test]
+fn test_delay_timeout() {
+    let error = client()
+        .execute_with_timeout(
+            &url("delay", &[("seconds", "1")]),
+            Duration::from_millis(100),
+        )
+        .unwrap_err();
+
+    assert!(matches!(error, XCallbackError::Timeout));
+}
+
+#[test]
+fn test_opener_timeout() {
+    // The URL is passed to the shell as $0, so the opener just takes its time
+    let client = XdgXCallbackClient::new().with_opener(vec!["sh", "-c", "sleep 3"]);
+    let start = Instant::now();
+    let error = client
+        .execute_with_timeout(&url("echo", &[]), Duration::from_millis(200))
+        .unwrap_err();
+
+    assert!(matches!(error, XCallbackError::Timeout));
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn test_malformed_callback() {
+    // Calls back to the wrong host, but with the request's callback id
+    let script = r#"id=$(echo "$1" | sed -n 's/.*callback_id%3D\([A-Za-z0-9]*\).*/\1/p')
+        exec "$0" "callback://elsewhere/success?callback_id=$id""#;
+    let client =
+        XdgXCallbackClient::new().with_opener(vec!["sh", "-c", script, env!("CARGO_BIN_EXE_cli")]);
+    let error = client
+        .execute_with_timeout(&url("echo", &[]), Duration::from_secs(5))
+        .unwrap_err();
+
+    assert!(
+        matches!(error, XCallbackError::MalformedCallback(_)),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn test_concurrent() {
+    let client = client();
+
+    let execute = |url: XCallbackUrl| client.execute(&url).map(|response| response.action_params);
+
+    thread::scope(|scope| {
+        let slow = scope.spawn(|| execute(url("delay", &[("seconds", "0.5")])));
+        let fast = scope.spawn(|| execute(url("echo", &[("n", "1")])));
+
+        assert_eq!(fast.join().unwrap().unwrap(), params(&[("n", "1")]));
+        assert_eq!(slow.join().unwrap().unwrap(), params(&[("seconds", "0.5")]));
+    });
+}
+
+/// A `HOME` of the tests' own, so callback can't touch the user's desktop settings
+fn home() -> PathBuf {
+    let home = env::temp_dir().join(format!("callback-home-{}", std::process::id()));
+    fs::create_dir_all(&home).unwrap();
+    home
+}
+
+/// `cli` with `HOME` and the XDG directories under `home`
+fn cli(home: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_cli"));
+    command
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("XDG_DATA_HOME", home.join(".local").join("share"));
+    command
+}
+
+/// Runs `cli` against `echo-target` and returns its exit status and output
+fn run_cli(args: &[&str]) -> (Option<i32>, String) {
+    let output = cli(&home())
+        .args(args)
+        .env(
+            "CALLBACK_OPENER",
+            format!(
+                "{} --opener {}",
+                env!("CARGO_BIN_EXE_echo-target"),
+                env!("CARGO_BIN_EXE_cli")
+            ),
+        )
+        .env("CALLBACK_NO_REGISTER", "1")
+        .output()
+        .unwrap();
+    (
+        output.status.code(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn test_cli_exit_codes() {
+    assert_eq!(
+        run_cli(&["echo-target", "echo", "n=1"]),
+        (Some(0), "success\nn=1\n".to_string())
+    );
+    assert_eq!(run_cli(&["echo-target", "fail"]).0, Some(1));
+    assert_eq!(run_cli(&["echo-target", "cancel"]).0, Some(2));
+    assert_eq!(run_cli(&["echo-target", "echo", "n"]).0, Some(64));
+    assert_eq!(run_cli(&["echo-target"]).0, Some(64));
+    assert_eq!(
+        run_cli(&["--timeout", "0.1", "echo-target", "delay", "seconds=1"]).0,
+        Some(124)
+    );
+    assert_eq!(run_cli(&["--help"]).0, Some(0));
+}
+
+#[test]
+fn test_cli_parameters() {
+    assert_eq!(
+        run_cli(&[
+            "echo-target",
+            "echo",
+            "title=Grüße & more",
+            "-p",
+            "query=a=b",
+            "--param",
+            "text",
+            "1 + 1 = 2",
+        ]),
+        (
+            Some(0),
+            "success\ntitle=Grüße & more\nquery=a=b\ntext=1 + 1 = 2\n".to_string()
+        )
+    );
+    assert_eq!(
+        run_cli(&[
+            "--encoded",
+            "echo-target",
+            "echo",
+            "title=My%20Note+Title",
+            "-p",
+            "text=50%",
+        ]),
+        (
+            Some(0),
+            "success\ntitle=My Note Title\ntext=50%\n".to_string()
+        )
+    );
+    // Only positional values refer to files, and \@ escapes them
+    assert_eq!(
+        run_cli(&[
+            "--encoded",
+            "echo-target",
+            "echo",
+            "a=\\@me",
+            "b=%40me",
+            "-p",
+            "c=@me",
+        ]),
+        (Some(0), "success\na=@me\nb=@me\nc=@me\n".to_string())
+    );
+    assert_eq!(run_cli(&["echo-target", "echo", "=value"]).0, Some(64));
+}
+
+#[test]
+fn test_cli_parse() {
+    assert_eq!(
+        run_cli(&["parse", "echo-target://x-callback-url/echo?n=1&x-source=test"]),
+        (
+            Some(0),
+            "scheme: echo-target\naction: echo\nparams:\n  n=1\ncallback params:\n  x-source=test\n"
+                .to_string()
+        )
+    );
+    assert_eq!(run_cli(&["parse", "echo-target://echo?n=1"]).0, Some(64));
+    // An action named parse still goes to the target app
+    assert_eq!(
+        run_cli(&["echo-target", "parse"]),
+        (
+            Some(1),
+            "error\nerrorMessage=Unknown action: parse\n".to_string()
+        )
+    );
+}
+
+#[test]
+fn test_cli_build() {
+    assert_eq!(
+        run_cli(&["build", "echo-target", "echo", "title=My Note"]),
+        (
+            Some(0),
+            "echo-target://x-callback-url/echo?title=My+Note\n".to_string()
+        )
+    );
+
+    let (status, output) = run_cli(&["--dry-run", "echo-target", "echo", "title=My Note"]);
+    assert_eq!(status, Some(0));
+    let url = XCallbackUrl::parse(output.trim_end()).unwrap();
+    assert_eq!(url.action_params().get("title"), Some("My Note"));
+    assert_eq!(url.callback_params().source(), Some("callback"));
+    assert!(url.callback_params().success().is_some());
+
+    // x- parameters are callback params, so x-source is not added twice
+    let (status, output) = run_cli(&["--dry-run", "echo-target", "echo", "x-source=me"]);
+    assert_eq!(status, Some(0));
+    assert_eq!(output.matches("x-source=").count(), 1);
+    let url = XCallbackUrl::parse(output.trim_end()).unwrap();
+    assert!(url.action_params().is_empty());
+    assert_eq!(url.callback_params().source(), Some("me"));
+}
+
+#[test]
+fn test_cli_registers_only_to_execute() {
+    let home = env::temp_dir().join(format!("callback-register-{}", std::process::id()));
+
+    for args in &[
+        &["--help"][..],
+        &["parse", "echo-target://x-callback-url/echo"],
+        &["build", "echo-target", "echo"],
+        &["--dry-run", "echo-target", "echo"],
+        &["echo-target"],
+    ] {
+        let output = cli(&home).args(*args).output().unwrap();
+        assert!(!String::from_utf8_lossy(&output.stderr).contains("register"));
+        assert!(!home.exists(), "registered for {:?}", args);
+    }
+}
+
+#[test]
+fn test_cli_no_register() {
+    assert_eq!(run_cli(&["echo-target", "echo"]).0, Some(0));
+    // Other tests share the directory, but none of them write to it either
+    assert_eq!(fs::read_dir(home()).unwrap().count(), 0);
+}
+
+#[test]
+fn test_cli_url() {
+    assert_eq!(
+        run_cli(&["url", "echo-target://x-callback-url/echo?n=1", "m=2"]),
+        (Some(0), "success\nn=1\nm=2\n".to_string())
+    );
+    // The URL's own x-success can't be used to hear back, so it is replaced
+    assert_eq!(
+        run_cli(&["echo-target://x-callback-url/echo?n=1&x-success=https%3A%2F%2Fexample.com"]),
+        (Some(0), "success\nn=1\n".to_string())
+    );
+    assert_eq!(
+        run_cli(&[
+            "build",
+            "echo-target://x-callback-url/echo?n=1&x-source=App&x-app=1",
+            "x-app=2",
+            "--param",
+            "m",
+            "a b",
+        ]),
+        (
+            Some(0),
+            "echo-target://x-callback-url/echo?n=1&x-source=App&m=a+b&x-app=2\n".to_string()
+        )
+    );
+    assert_eq!(run_cli(&["url"]).0, Some(64));
+    assert_eq!(run_cli(&["url", "echo-target://echo"]).0, Some(64));
+}